@@ -0,0 +1,5 @@
+ALTER TABLE blogs
+    DROP CONSTRAINT IF EXISTS blogs_title_check,
+    DROP CONSTRAINT IF EXISTS blogs_author_check,
+    DROP CONSTRAINT IF EXISTS blogs_url_check,
+    DROP CONSTRAINT IF EXISTS blogs_likes_check;
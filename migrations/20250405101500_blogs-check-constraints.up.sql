@@ -0,0 +1,6 @@
+-- Same rules as the validation of BlogPostPayload and BlogUpdatePayload
+ALTER TABLE blogs
+    ADD CONSTRAINT blogs_title_check CHECK (char_length(btrim(title)) BETWEEN 1 AND 300),
+    ADD CONSTRAINT blogs_author_check CHECK (char_length(btrim(author)) BETWEEN 1 AND 200),
+    ADD CONSTRAINT blogs_url_check CHECK (url ~* '^https?://' AND char_length(url) <= 2048),
+    ADD CONSTRAINT blogs_likes_check CHECK (likes >= 0);
@@ -0,0 +1,8 @@
+DROP INDEX IF EXISTS blogs_domain_idx;
+DROP INDEX IF EXISTS blogs_created_at_idx;
+DROP INDEX IF EXISTS blogs_likes_idx;
+DROP INDEX IF EXISTS blogs_title_trgm_idx;
+DROP INDEX IF EXISTS blogs_author_trgm_idx;
+DROP INDEX IF EXISTS blogs_author_idx;
+
+ALTER TABLE blogs DROP COLUMN IF EXISTS domain;
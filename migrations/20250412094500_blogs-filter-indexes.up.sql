@@ -0,0 +1,14 @@
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+-- host of the url without a leading www., used by the domain filter
+ALTER TABLE blogs
+    ADD COLUMN domain TEXT GENERATED ALWAYS AS (
+        lower(substring(url from '^[A-Za-z][A-Za-z0-9+.-]*://(?:www\.)?([^/:?#]+)'))
+    ) STORED;
+
+CREATE INDEX blogs_author_idx ON blogs (author);
+CREATE INDEX blogs_author_trgm_idx ON blogs USING GIN (author gin_trgm_ops);
+CREATE INDEX blogs_title_trgm_idx ON blogs USING GIN (title gin_trgm_ops);
+CREATE INDEX blogs_likes_idx ON blogs (likes, id);
+CREATE INDEX blogs_created_at_idx ON blogs (created_at, id);
+CREATE INDEX blogs_domain_idx ON blogs (domain);
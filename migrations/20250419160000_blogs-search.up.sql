@@ -0,0 +1,8 @@
+ALTER TABLE blogs
+    ADD COLUMN search TSVECTOR GENERATED ALWAYS AS (
+        setweight(to_tsvector('english', title), 'A')
+        || setweight(to_tsvector('english', author), 'B')
+        || setweight(to_tsvector('english', url), 'C')
+    ) STORED;
+
+CREATE INDEX blogs_search_idx ON blogs USING GIN (search);
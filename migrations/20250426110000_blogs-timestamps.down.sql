@@ -0,0 +1,8 @@
+DROP TRIGGER IF EXISTS blogs_set_updated_at ON blogs;
+DROP FUNCTION IF EXISTS set_updated_at();
+DROP INDEX IF EXISTS blogs_updated_at_idx;
+
+ALTER TABLE blogs
+    DROP COLUMN IF EXISTS updated_at,
+    ALTER COLUMN created_at DROP NOT NULL,
+    ALTER COLUMN created_at TYPE TIMESTAMP USING created_at AT TIME ZONE 'UTC';
@@ -0,0 +1,25 @@
+-- existing values were written by now() in the server's time zone, assume UTC
+ALTER TABLE blogs
+    ALTER COLUMN created_at TYPE TIMESTAMPTZ USING created_at AT TIME ZONE 'UTC';
+UPDATE blogs SET created_at = now() WHERE created_at IS NULL;
+ALTER TABLE blogs
+    ALTER COLUMN created_at SET NOT NULL,
+    ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now();
+UPDATE blogs SET updated_at = created_at;
+
+CREATE INDEX blogs_updated_at_idx ON blogs (updated_at, id);
+
+CREATE OR REPLACE FUNCTION set_updated_at() RETURNS TRIGGER AS $$
+BEGIN
+    NEW.updated_at = now();
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+-- only writes that change the blog count as updates
+CREATE TRIGGER blogs_set_updated_at
+    BEFORE UPDATE ON blogs
+    FOR EACH ROW
+    WHEN ((OLD.title, OLD.author, OLD.url, OLD.likes)
+        IS DISTINCT FROM (NEW.title, NEW.author, NEW.url, NEW.likes))
+    EXECUTE FUNCTION set_updated_at();
@@ -0,0 +1,10 @@
+DROP TRIGGER IF EXISTS blogs_set_updated_at ON blogs;
+CREATE TRIGGER blogs_set_updated_at
+    BEFORE UPDATE ON blogs
+    FOR EACH ROW
+    WHEN ((OLD.title, OLD.author, OLD.url, OLD.likes)
+        IS DISTINCT FROM (NEW.title, NEW.author, NEW.url, NEW.likes))
+    EXECUTE FUNCTION set_updated_at();
+
+DROP TABLE IF EXISTS blog_likes;
+DROP FUNCTION IF EXISTS count_blog_likes();
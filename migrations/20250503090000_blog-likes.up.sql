@@ -0,0 +1,32 @@
+-- one row per caller liking a blog, `liker` is a user or client id
+CREATE TABLE blog_likes (
+    blog_id INT NOT NULL REFERENCES blogs (id) ON DELETE CASCADE,
+    liker TEXT NOT NULL CHECK (length(liker) BETWEEN 1 AND 100),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (blog_id, liker)
+);
+
+-- blogs.likes counts the rows above, likes given before this table existed are kept
+CREATE OR REPLACE FUNCTION count_blog_likes() RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        UPDATE blogs SET likes = likes + 1 WHERE id = NEW.blog_id;
+    ELSE
+        UPDATE blogs SET likes = likes - 1 WHERE id = OLD.blog_id;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER blog_likes_count
+    AFTER INSERT OR DELETE ON blog_likes
+    FOR EACH ROW
+    EXECUTE FUNCTION count_blog_likes();
+
+-- a like is not a change of the blog
+DROP TRIGGER blogs_set_updated_at ON blogs;
+CREATE TRIGGER blogs_set_updated_at
+    BEFORE UPDATE ON blogs
+    FOR EACH ROW
+    WHEN ((OLD.title, OLD.author, OLD.url) IS DISTINCT FROM (NEW.title, NEW.author, NEW.url))
+    EXECUTE FUNCTION set_updated_at();
@@ -0,0 +1,5 @@
+DROP FUNCTION IF EXISTS user_blogs(INT);
+DROP FUNCTION IF EXISTS blog_user(INT);
+DROP INDEX IF EXISTS blogs_user_id_idx;
+ALTER TABLE blogs DROP COLUMN IF EXISTS user_id;
+DROP TABLE IF EXISTS users;
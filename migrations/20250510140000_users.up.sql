@@ -0,0 +1,31 @@
+CREATE TABLE users (
+    id SERIAL PRIMARY KEY,
+    username TEXT NOT NULL CONSTRAINT users_username_key UNIQUE
+        CONSTRAINT users_username_check CHECK (username ~ '^[A-Za-z0-9_.-]{3,50}$'),
+    name TEXT NOT NULL CONSTRAINT users_name_check CHECK (length(btrim(name)) BETWEEN 1 AND 200),
+    password_hash TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- blogs added before users existed have no submitter
+ALTER TABLE blogs
+    ADD COLUMN user_id INT REFERENCES users (id) ON DELETE SET NULL;
+CREATE INDEX blogs_user_id_idx ON blogs (user_id);
+
+-- public part of the user who submitted a blog, embedded in the blog JSON
+CREATE OR REPLACE FUNCTION blog_user(user_id INT) RETURNS JSONB AS $$
+    SELECT jsonb_build_object('id', id, 'username', username, 'name', name)
+    FROM users WHERE users.id = blog_user.user_id
+$$ LANGUAGE sql STABLE;
+
+-- blogs submitted by a user, embedded in the user JSON
+CREATE OR REPLACE FUNCTION user_blogs(user_id INT) RETURNS JSONB AS $$
+    SELECT COALESCE(
+        jsonb_agg(
+            jsonb_build_object('id', id, 'title', title, 'author', author, 'url', url, 'likes', likes)
+            ORDER BY id
+        ),
+        '[]'
+    )
+    FROM blogs WHERE blogs.user_id = user_blogs.user_id
+$$ LANGUAGE sql STABLE;
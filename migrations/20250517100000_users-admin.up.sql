@@ -0,0 +1,3 @@
+-- admins may change every blog, there is no API to grant it:
+-- UPDATE users SET is_admin = TRUE WHERE username = '...';
+ALTER TABLE users ADD COLUMN is_admin BOOLEAN NOT NULL DEFAULT FALSE;
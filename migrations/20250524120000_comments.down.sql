@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS comments;
+DROP FUNCTION IF EXISTS count_blog_comments();
+ALTER TABLE blogs DROP COLUMN IF EXISTS comment_count;
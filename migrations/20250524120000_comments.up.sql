@@ -0,0 +1,41 @@
+CREATE TABLE comments (
+    id SERIAL PRIMARY KEY,
+    blog_id INT NOT NULL REFERENCES blogs (id) ON DELETE CASCADE,
+    -- replies belong to the same blog as the comment they answer
+    parent_id INT,
+    user_id INT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    body TEXT NOT NULL CONSTRAINT comments_body_check CHECK (length(btrim(body)) BETWEEN 1 AND 5000),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    CONSTRAINT comments_blog_id_id_key UNIQUE (blog_id, id),
+    CONSTRAINT comments_parent_fkey FOREIGN KEY (blog_id, parent_id)
+        REFERENCES comments (blog_id, id) ON DELETE CASCADE
+);
+
+CREATE INDEX comments_parent_id_idx ON comments (parent_id);
+CREATE INDEX comments_user_id_idx ON comments (user_id);
+
+CREATE TRIGGER comments_set_updated_at
+    BEFORE UPDATE ON comments
+    FOR EACH ROW
+    WHEN (OLD.body IS DISTINCT FROM NEW.body)
+    EXECUTE FUNCTION set_updated_at();
+
+-- blogs.comment_count counts the comments of a blog, replies included
+ALTER TABLE blogs ADD COLUMN comment_count INT NOT NULL DEFAULT 0;
+
+CREATE OR REPLACE FUNCTION count_blog_comments() RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        UPDATE blogs SET comment_count = comment_count + 1 WHERE id = NEW.blog_id;
+    ELSE
+        UPDATE blogs SET comment_count = comment_count - 1 WHERE id = OLD.blog_id;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER comments_count
+    AFTER INSERT OR DELETE ON comments
+    FOR EACH ROW
+    EXECUTE FUNCTION count_blog_comments();
@@ -0,0 +1,3 @@
+DROP FUNCTION IF EXISTS blog_tag_names(INT);
+DROP TABLE IF EXISTS blog_tags;
+DROP TABLE IF EXISTS tags;
@@ -0,0 +1,21 @@
+-- tag names are stored normalized, trimmed and lowercase
+CREATE TABLE tags (
+    id SERIAL PRIMARY KEY,
+    name TEXT NOT NULL CONSTRAINT tags_name_key UNIQUE
+        CONSTRAINT tags_name_check CHECK (name = lower(btrim(name)) AND length(name) BETWEEN 1 AND 50)
+);
+
+CREATE TABLE blog_tags (
+    blog_id INT NOT NULL REFERENCES blogs (id) ON DELETE CASCADE,
+    tag_id INT NOT NULL REFERENCES tags (id) ON DELETE CASCADE,
+    PRIMARY KEY (blog_id, tag_id)
+);
+
+CREATE INDEX blog_tags_tag_id_idx ON blog_tags (tag_id);
+
+-- names of the tags of a blog, embedded in the blog JSON
+CREATE OR REPLACE FUNCTION blog_tag_names(blog_id INT) RETURNS TEXT[] AS $$
+    SELECT COALESCE(array_agg(tags.name ORDER BY tags.name), '{}')
+    FROM blog_tags JOIN tags ON tags.id = blog_tags.tag_id
+    WHERE blog_tags.blog_id = blog_tag_names.blog_id
+$$ LANGUAGE sql STABLE;
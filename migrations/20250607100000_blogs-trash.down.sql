@@ -0,0 +1,17 @@
+DROP FUNCTION IF EXISTS tag_blog_count(INT);
+
+CREATE OR REPLACE FUNCTION user_blogs(user_id INT) RETURNS JSONB AS $$
+    SELECT COALESCE(
+        jsonb_agg(
+            jsonb_build_object('id', id, 'title', title, 'author', author, 'url', url, 'likes', likes)
+            ORDER BY id
+        ),
+        '[]'
+    )
+    FROM blogs WHERE blogs.user_id = user_blogs.user_id
+$$ LANGUAGE sql STABLE;
+
+-- trashed blogs were deleted, they do not come back
+DELETE FROM blogs WHERE deleted_at IS NOT NULL;
+DROP INDEX IF EXISTS blogs_deleted_at_idx;
+ALTER TABLE blogs DROP COLUMN IF EXISTS deleted_at;
@@ -0,0 +1,23 @@
+-- deleted blogs stay in the trash until they are restored or purged
+ALTER TABLE blogs ADD COLUMN deleted_at TIMESTAMPTZ;
+
+CREATE INDEX blogs_deleted_at_idx ON blogs (deleted_at) WHERE deleted_at IS NOT NULL;
+
+-- trashed blogs are no longer listed on their user
+CREATE OR REPLACE FUNCTION user_blogs(user_id INT) RETURNS JSONB AS $$
+    SELECT COALESCE(
+        jsonb_agg(
+            jsonb_build_object('id', id, 'title', title, 'author', author, 'url', url, 'likes', likes)
+            ORDER BY id
+        ),
+        '[]'
+    )
+    FROM blogs WHERE blogs.user_id = user_blogs.user_id AND deleted_at IS NULL
+$$ LANGUAGE sql STABLE;
+
+-- number of blogs outside the trash labelled with a tag
+CREATE OR REPLACE FUNCTION tag_blog_count(tag_id INT) RETURNS BIGINT AS $$
+    SELECT COUNT(*)
+    FROM blog_tags JOIN blogs ON blogs.id = blog_tags.blog_id
+    WHERE blog_tags.tag_id = tag_blog_count.tag_id AND blogs.deleted_at IS NULL
+$$ LANGUAGE sql STABLE;
@@ -0,0 +1,3 @@
+DROP FUNCTION IF EXISTS blog_values(INT);
+DROP TABLE IF EXISTS blog_revisions;
+DROP TYPE IF EXISTS revision_action;
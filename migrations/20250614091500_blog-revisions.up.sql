@@ -0,0 +1,30 @@
+CREATE TYPE revision_action AS ENUM ('create', 'update', 'delete', 'restore', 'revert');
+
+-- every change of a blog, numbered from 1 per blog
+CREATE TABLE blog_revisions (
+    id SERIAL PRIMARY KEY,
+    blog_id INT NOT NULL REFERENCES blogs(id) ON DELETE CASCADE,
+    rev INT NOT NULL,
+    action revision_action NOT NULL,
+    -- the user who made the change, kept when the user is gone
+    user_id INT REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    -- the fields of the blog before and after the change, NULL when it did not exist
+    old_values JSONB,
+    new_values JSONB,
+    CONSTRAINT blog_revisions_blog_id_rev_key UNIQUE (blog_id, rev)
+);
+
+CREATE INDEX blog_revisions_user_id_idx ON blog_revisions (user_id);
+
+-- the fields of a blog that revisions keep track of
+CREATE OR REPLACE FUNCTION blog_values(blog_id INT) RETURNS JSONB AS $$
+    SELECT jsonb_build_object(
+        'title', title, 'author', author, 'url', url, 'tags', to_jsonb(blog_tag_names(id))
+    )
+    FROM blogs WHERE blogs.id = blog_values.blog_id
+$$ LANGUAGE sql STABLE;
+
+-- blogs added so far start their history with what they are now
+INSERT INTO blog_revisions (blog_id, rev, action, user_id, created_at, new_values)
+SELECT id, 1, 'create', user_id, created_at, blog_values(id) FROM blogs;
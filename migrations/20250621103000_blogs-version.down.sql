@@ -0,0 +1,6 @@
+DROP TRIGGER IF EXISTS tags_bump_version ON tags;
+DROP TRIGGER IF EXISTS blog_tags_bump_version ON blog_tags;
+DROP FUNCTION IF EXISTS bump_tagged_blog_version();
+DROP TRIGGER IF EXISTS blogs_bump_version ON blogs;
+DROP FUNCTION IF EXISTS bump_blog_version();
+ALTER TABLE blogs DROP COLUMN IF EXISTS version;
@@ -0,0 +1,44 @@
+-- version of a blog for ETag and If-Match, goes up with every change of what GET returns
+ALTER TABLE blogs ADD COLUMN version INT NOT NULL DEFAULT 1;
+
+CREATE OR REPLACE FUNCTION bump_blog_version() RETURNS TRIGGER AS $$
+BEGIN
+    -- writes that set the version themselves keep it
+    IF NEW.version = OLD.version THEN
+        NEW.version = OLD.version + 1;
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER blogs_bump_version
+    BEFORE UPDATE ON blogs
+    FOR EACH ROW
+    WHEN ((OLD.title, OLD.author, OLD.url, OLD.likes, OLD.comment_count, OLD.user_id, OLD.deleted_at)
+        IS DISTINCT FROM (NEW.title, NEW.author, NEW.url, NEW.likes, NEW.comment_count, NEW.user_id, NEW.deleted_at))
+    EXECUTE FUNCTION bump_blog_version();
+
+-- tags live in other tables, changing them changes the blogs using them
+CREATE OR REPLACE FUNCTION bump_tagged_blog_version() RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_TABLE_NAME = 'tags' THEN
+        UPDATE blogs SET version = version + 1
+        WHERE id IN (SELECT blog_id FROM blog_tags WHERE tag_id = NEW.id);
+    ELSIF TG_OP = 'INSERT' THEN
+        UPDATE blogs SET version = version + 1 WHERE id = NEW.blog_id;
+    ELSE
+        UPDATE blogs SET version = version + 1 WHERE id = OLD.blog_id;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER blog_tags_bump_version
+    AFTER INSERT OR DELETE ON blog_tags
+    FOR EACH ROW
+    EXECUTE FUNCTION bump_tagged_blog_version();
+
+CREATE TRIGGER tags_bump_version
+    AFTER UPDATE OF name ON tags
+    FOR EACH ROW
+    EXECUTE FUNCTION bump_tagged_blog_version();
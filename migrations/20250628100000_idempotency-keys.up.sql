@@ -0,0 +1,14 @@
+-- responses of writes sent with an Idempotency-Key, replayed when the request is retried
+CREATE TABLE idempotency_keys (
+    key TEXT PRIMARY KEY CHECK (length(key) BETWEEN 1 AND 255),
+    -- hash of the method, path, caller and body of the request
+    fingerprint BYTEA NOT NULL,
+    -- NULL while the first request with the key is running
+    status SMALLINT,
+    headers JSONB,
+    body BYTEA,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX idempotency_keys_expires_at_idx ON idempotency_keys (expires_at);
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS blogs_canonical_url_key;
+ALTER TABLE blogs DROP COLUMN IF EXISTS canonical_url;
+DROP FUNCTION IF EXISTS canonical_url(TEXT);
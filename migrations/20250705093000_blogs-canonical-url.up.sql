@@ -0,0 +1,92 @@
+-- url without the differences that do not change the page it points to: the scheme, the case
+-- of the host, a leading www., the default port of the scheme, trailing slashes, the fragment,
+-- tracking parameters and the order of the query parameters
+CREATE OR REPLACE FUNCTION canonical_url(url TEXT) RETURNS TEXT AS $$
+    SELECT COALESCE(
+        lower(CASE lower(parts[1])
+            WHEN 'http' THEN regexp_replace(host, ':80$', '')
+            WHEN 'https' THEN regexp_replace(host, ':443$', '')
+            ELSE host
+        END)
+        || regexp_replace(parts[3], '/+$', '')
+        || COALESCE('?' || (
+            SELECT string_agg(param, '&' ORDER BY param COLLATE "C")
+            FROM unnest(string_to_array(parts[4], '&')) AS param
+            WHERE param <> ''
+                AND param !~* '^(utm_[^=]*|fbclid|gclid|dclid|msclkid|yclid|igshid|mc_cid|mc_eid|_hsenc|_hsmi)(=|$)'
+        ), ''),
+        url
+    )
+    FROM (
+        SELECT parts, regexp_replace(parts[2], '^www\.', '', 'i') AS host
+        FROM regexp_match(url, '^([A-Za-z][A-Za-z0-9+.-]*)://([^/?#]*)([^?#]*)(?:\?([^#]*))?') AS parts
+    ) AS matched
+$$ LANGUAGE sql IMMUTABLE;
+
+ALTER TABLE blogs
+    ADD COLUMN canonical_url TEXT GENERATED ALWAYS AS (canonical_url(url)) STORED;
+
+-- blogs submitted again before there was a check are merged into the first submission, as
+-- POST /blogs/{id}/merge does: their likes, comments and tags move over and they are deleted
+CREATE TEMPORARY TABLE merged_blogs AS
+SELECT id, into_id FROM (
+    SELECT id, first_value(id) OVER (PARTITION BY canonical_url ORDER BY id) AS into_id
+    FROM blogs WHERE deleted_at IS NULL
+) AS numbered
+WHERE id <> into_id;
+
+CREATE TEMPORARY TABLE merged_into AS
+SELECT DISTINCT into_id AS id, blog_values(into_id) AS old_values FROM merged_blogs;
+
+-- a liker of several of the blogs keeps one like, moving rows does not fire the counting
+-- triggers
+UPDATE blog_likes SET blog_id = merged.into_id
+FROM merged_blogs merged
+WHERE blog_likes.blog_id = merged.id
+    AND NOT EXISTS (
+        SELECT 1 FROM blog_likes other
+        WHERE other.liker = blog_likes.liker
+            AND (other.blog_id = merged.into_id
+                OR other.blog_id IN (
+                    SELECT id FROM merged_blogs
+                    WHERE into_id = merged.into_id AND id < blog_likes.blog_id
+                ))
+    );
+
+-- the likes left behind are from likers already counted for the blog merged into
+UPDATE blogs SET
+    likes = blogs.likes + added.likes,
+    comment_count = blogs.comment_count + added.comment_count
+FROM (
+    SELECT merged.into_id,
+        SUM(source.likes - (SELECT COUNT(*) FROM blog_likes WHERE blog_id = source.id)) AS likes,
+        SUM(source.comment_count) AS comment_count
+    FROM merged_blogs merged JOIN blogs source ON source.id = merged.id
+    GROUP BY merged.into_id
+) AS added
+WHERE blogs.id = added.into_id;
+
+UPDATE comments SET blog_id = merged.into_id
+FROM merged_blogs merged
+WHERE comments.blog_id = merged.id;
+
+INSERT INTO blog_tags (blog_id, tag_id)
+SELECT DISTINCT merged.into_id, blog_tags.tag_id
+FROM merged_blogs merged JOIN blog_tags ON blog_tags.blog_id = merged.id
+ON CONFLICT DO NOTHING;
+
+DELETE FROM blogs WHERE id IN (SELECT id FROM merged_blogs);
+
+INSERT INTO blog_revisions (blog_id, rev, action, old_values, new_values)
+SELECT id,
+    (SELECT COALESCE(MAX(rev), 0) + 1 FROM blog_revisions WHERE blog_id = merged_into.id),
+    'update',
+    old_values,
+    blog_values(id)
+FROM merged_into
+WHERE old_values IS DISTINCT FROM blog_values(id);
+
+DROP TABLE merged_blogs, merged_into;
+
+-- a blog in the trash does not stop the same url from being submitted again
+CREATE UNIQUE INDEX blogs_canonical_url_key ON blogs (canonical_url) WHERE deleted_at IS NULL;
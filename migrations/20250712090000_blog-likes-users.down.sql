@@ -0,0 +1,8 @@
+ALTER TABLE blog_likes DROP CONSTRAINT blog_likes_pkey;
+ALTER TABLE blog_likes ADD COLUMN liker TEXT;
+UPDATE blog_likes SET liker = user_id::TEXT;
+ALTER TABLE blog_likes
+    ALTER COLUMN liker SET NOT NULL,
+    ADD CHECK (length(liker) BETWEEN 1 AND 100),
+    DROP COLUMN user_id,
+    ADD PRIMARY KEY (blog_id, liker);
@@ -0,0 +1,22 @@
+-- likes belong to logged in users, a client id is whatever the caller sends
+ALTER TABLE blog_likes ADD COLUMN user_id INT REFERENCES users (id) ON DELETE CASCADE;
+
+-- likers that name a user, by id or username, keep their likes; an id wins over a username
+UPDATE blog_likes SET user_id = (
+    SELECT id FROM users
+    WHERE id::TEXT = blog_likes.liker OR username = blog_likes.liker
+    ORDER BY id::TEXT = blog_likes.liker DESC
+    LIMIT 1
+);
+
+-- the other likes cannot be traced to a user, nobody could take them back, and a user who
+-- liked under both names keeps one like. The counting trigger takes them off blogs.likes
+DELETE FROM blog_likes WHERE user_id IS NULL;
+DELETE FROM blog_likes dup USING blog_likes kept
+WHERE dup.blog_id = kept.blog_id AND dup.user_id = kept.user_id AND dup.liker > kept.liker;
+
+ALTER TABLE blog_likes
+    DROP CONSTRAINT blog_likes_pkey,
+    DROP COLUMN liker,
+    ALTER COLUMN user_id SET NOT NULL,
+    ADD PRIMARY KEY (blog_id, user_id);
@@ -0,0 +1,8 @@
+-- keys are chosen by the callers, so each user gets their own; the stored responses are only
+-- kept for a while and are dropped instead of guessing their user
+DELETE FROM idempotency_keys;
+
+ALTER TABLE idempotency_keys
+    DROP CONSTRAINT idempotency_keys_pkey,
+    ADD COLUMN user_id INT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    ADD PRIMARY KEY (user_id, key);
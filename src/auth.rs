@@ -0,0 +1,322 @@
+use axum::{
+    extract::FromRequestParts,
+    http::{header, request::Parts},
+};
+use chrono::{DateTime, Utc};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::{
+    blogs_api::blog_not_found, comments_api::comment_not_found, config::AuthConfig,
+    errors::AppError, extractors::AppPath,
+};
+
+/// Payload of the tokens issued by `POST /login`
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Claims {
+    /// Id of the user
+    pub sub: i32,
+    pub username: String,
+    /// Issue and expiry time, seconds since the epoch
+    pub iat: i64,
+    pub exp: i64,
+}
+
+/// The user who sent a valid `Authorization: Bearer <token>` header
+///
+/// Rejects the request with 401 when the header is missing or the token is expired or tampered
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    pub id: i32,
+}
+
+impl<S> FromRequestParts<S> for AuthUser
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let config = parts
+            .extensions
+            .get::<AuthConfig>()
+            .ok_or_else(|| AppError::Internal("AuthConfig extension is missing".to_string()))?;
+        let token = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim)
+            .ok_or_else(|| AppError::Unauthorized("Missing bearer token".to_string()))?;
+
+        let claims = verify_token(config, token)?;
+        Ok(AuthUser { id: claims.sub })
+    }
+}
+
+/// A logged in user who is an admin, everyone else gets 403
+#[derive(Debug, Clone, Copy)]
+pub struct AdminUser;
+
+impl<S> FromRequestParts<S> for AdminUser
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let user = AuthUser::from_request_parts(parts, state).await?;
+        let pool = parts
+            .extensions
+            .get::<PgPool>()
+            .ok_or_else(|| AppError::Internal("PgPool extension is missing".to_string()))?;
+
+        let is_admin = sqlx::query_scalar!(
+            r#"SELECT EXISTS (SELECT 1 FROM users WHERE id = $1 AND is_admin) AS "is_admin!""#,
+            user.id
+        )
+        .fetch_one(pool)
+        .await?;
+        if !is_admin {
+            return Err(AppError::Forbidden("Only admins can do this".to_string()));
+        }
+
+        Ok(AdminUser)
+    }
+}
+
+/// Id of the blog in the path, which the logged in user is allowed to change
+///
+/// Only the user who submitted a blog and admins may change or delete it, everyone else gets
+/// 403. Blogs added before there were users can only be changed by admins. Blogs in the trash
+/// are not found
+#[derive(Debug, Clone, Copy)]
+pub struct BlogOwner(pub i32);
+
+impl<S> FromRequestParts<S> for BlogOwner
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        blog_owner(parts, state, false).await.map(BlogOwner)
+    }
+}
+
+/// Id of the blog in the path, which is in the trash and the logged in user is allowed to restore
+///
+/// The same users who could delete a blog may restore it, blogs outside the trash are not found
+#[derive(Debug, Clone, Copy)]
+pub struct TrashedBlogOwner(pub i32);
+
+impl<S> FromRequestParts<S> for TrashedBlogOwner
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        blog_owner(parts, state, true).await.map(TrashedBlogOwner)
+    }
+}
+
+/// The blog id of a path, which may hold more parameters like `/blogs/{id}/revisions/{rev}`
+#[derive(Deserialize)]
+struct BlogPath {
+    id: i32,
+}
+
+async fn blog_owner<S>(parts: &mut Parts, state: &S, trashed: bool) -> Result<i32, AppError>
+where
+    S: Send + Sync,
+{
+    let user = AuthUser::from_request_parts(parts, state).await?;
+    let AppPath(BlogPath { id }) = AppPath::from_request_parts(parts, state).await?;
+    let pool = parts
+        .extensions
+        .get::<PgPool>()
+        .ok_or_else(|| AppError::Internal("PgPool extension is missing".to_string()))?;
+
+    let blog = sqlx::query!(
+        r#"SELECT user_id IS NOT DISTINCT FROM $2 AS "is_owner!",
+            EXISTS (SELECT 1 FROM users WHERE id = $2 AND is_admin) AS "is_admin!"
+        FROM blogs WHERE id = $1 AND (deleted_at IS NOT NULL) = $3"#,
+        id,
+        user.id,
+        trashed
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| blog_not_found(id))?;
+    if !blog.is_owner && !blog.is_admin {
+        return Err(AppError::Forbidden(format!(
+            "Only the user who submitted the blog with id={id} can change it"
+        )));
+    }
+
+    Ok(id)
+}
+
+/// Id of the comment in the path, which the logged in user is allowed to change
+///
+/// Only the user who wrote a comment and admins may edit or delete it, everyone else gets 403
+#[derive(Debug, Clone, Copy)]
+pub struct CommentAuthor(pub i32);
+
+impl<S> FromRequestParts<S> for CommentAuthor
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let user = AuthUser::from_request_parts(parts, state).await?;
+        let AppPath(id) = AppPath::<i32>::from_request_parts(parts, state).await?;
+        let pool = parts
+            .extensions
+            .get::<PgPool>()
+            .ok_or_else(|| AppError::Internal("PgPool extension is missing".to_string()))?;
+
+        let comment = sqlx::query!(
+            r#"SELECT user_id = $2 AS "is_author!",
+                EXISTS (SELECT 1 FROM users WHERE id = $2 AND is_admin) AS "is_admin!"
+            FROM comments WHERE id = $1
+                AND EXISTS (SELECT 1 FROM blogs WHERE id = blog_id AND deleted_at IS NULL)"#,
+            id,
+            user.id
+        )
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| comment_not_found(id))?;
+        if !comment.is_author && !comment.is_admin {
+            return Err(AppError::Forbidden(format!(
+                "Only the user who wrote the comment with id={id} can change it"
+            )));
+        }
+
+        Ok(CommentAuthor(id))
+    }
+}
+
+/// Signed token for a user, with its expiry time
+pub fn issue_token(
+    config: &AuthConfig,
+    user_id: i32,
+    username: &str,
+) -> Result<(String, DateTime<Utc>), AppError> {
+    let now = Utc::now();
+    let expires_at = now + config.token_lifetime;
+    let claims = Claims {
+        sub: user_id,
+        username: username.to_string(),
+        iat: now.timestamp(),
+        exp: expires_at.timestamp(),
+    };
+    let token = encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(config.jwt_secret.as_bytes()),
+    )
+    .map_err(|e| AppError::Internal(format!("Failed to sign token: {e}")))?;
+
+    Ok((token, expires_at))
+}
+
+fn verify_token(config: &AuthConfig, token: &str) -> Result<Claims, AppError> {
+    let mut validation = Validation::default();
+    // tokens are issued by this server, clocks cannot disagree
+    validation.leeway = 0;
+    decode::<Claims>(
+        token,
+        &DecodingKey::from_secret(config.jwt_secret.as_bytes()),
+        &validation,
+    )
+    .map(|data| data.claims)
+    .map_err(|e| match e.kind() {
+        jsonwebtoken::errors::ErrorKind::ExpiredSignature => {
+            AppError::Unauthorized("Token has expired".to_string())
+        }
+        _ => AppError::Unauthorized("Invalid token".to_string()),
+    })
+}
+
+#[cfg(test)]
+mod auth_test {
+    use crate::test_helper::{insert_test_values, setup_test_context, test_auth_config};
+    use axum::http::{header, StatusCode};
+    use chrono::{Duration, Utc};
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use rstest::*;
+    use serde_json::json;
+
+    use super::{issue_token, Claims};
+
+    fn expired_token() -> String {
+        let claims = Claims {
+            sub: 1,
+            username: "root".to_string(),
+            iat: (Utc::now() - Duration::hours(2)).timestamp(),
+            exp: (Utc::now() - Duration::hours(1)).timestamp(),
+        };
+        encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(test_auth_config().jwt_secret.as_bytes()),
+        )
+        .unwrap()
+    }
+
+    fn tampered_token() -> String {
+        let (token, _) = issue_token(&test_auth_config(), 1, "root").unwrap();
+        // swap the payload for one claiming another user, keeping the signature
+        let parts: Vec<&str> = token.split('.').collect();
+        let (other, _) = issue_token(&test_auth_config(), 2, "admin").unwrap();
+        let other_payload = other.split('.').nth(1).unwrap();
+        format!("{}.{}.{}", parts[0], other_payload, parts[2])
+    }
+
+    // write routes reject requests without a valid token, nothing is written
+    #[rstest]
+    #[case::missing(None)]
+    #[case::not_bearer(Some("Basic cm9vdDpzZWtyZXQ=".to_string()))]
+    #[case::garbage(Some("Bearer abc".to_string()))]
+    #[case::expired(Some(format!("Bearer {}", expired_token())))]
+    #[case::tampered(Some(format!("Bearer {}", tampered_token())))]
+    #[tokio::test]
+    async fn write_routes_require_token(#[case] authorization: Option<String>) {
+        let mut ctx = setup_test_context().await;
+        insert_test_values(&ctx.pool)
+            .await
+            .expect("Expected insert statement to work");
+        ctx.login_as("root").await;
+        ctx.server.clear_headers();
+        if let Some(authorization) = authorization {
+            ctx.server.add_header(header::AUTHORIZATION, authorization);
+        }
+
+        let blog = json!({"title": "t", "author": "a", "url": "http://a.com"});
+        let responses = [
+            ctx.server.post("/blogs").json(&blog).await,
+            ctx.server.put("/blogs/1").json(&blog).await,
+            ctx.server.patch("/blogs/1").json(&blog).await,
+            ctx.server.delete("/blogs/1").await,
+        ];
+        for response in responses {
+            response.assert_status(StatusCode::UNAUTHORIZED);
+            response.assert_header(header::WWW_AUTHENTICATE, "Bearer");
+        }
+        ctx.server
+            .get("/blogs")
+            .await
+            .assert_header("x-total-count", "6");
+        assert_eq!(
+            "React patterns",
+            ctx.server.get("/blogs/1").await.json::<serde_json::Value>()["title"]
+        );
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+}
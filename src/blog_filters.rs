@@ -0,0 +1,319 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+use sqlx::{Postgres, QueryBuilder};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    errors::AppError,
+    models::{normalize_tags, Blog},
+    pagination::{invalid_cursor, Page, PageMode},
+};
+
+/// Query parameters narrowing down a list of blogs
+#[derive(Deserialize, IntoParams, Debug, Default)]
+#[into_params(parameter_in = Query)]
+pub struct BlogFilter {
+    /// Exact author name
+    pub author: Option<String>,
+    /// Case-insensitive part of the author name
+    pub author_contains: Option<String>,
+    /// Case-insensitive part of the title
+    pub title: Option<String>,
+    /// Minimum number of likes
+    pub min_likes: Option<i32>,
+    /// Maximum number of likes
+    pub max_likes: Option<i32>,
+    /// Only blogs created at or after this time (RFC 3339)
+    pub created_after: Option<DateTime<Utc>>,
+    /// Only blogs created before this time (RFC 3339)
+    pub created_before: Option<DateTime<Utc>>,
+    /// Only blogs last modified at or after this time (RFC 3339)
+    pub updated_after: Option<DateTime<Utc>>,
+    /// Only blogs last modified before this time (RFC 3339)
+    pub updated_before: Option<DateTime<Utc>>,
+    /// Host of the url, a leading `www.` is ignored
+    #[param(example = "blog.cleancoder.com")]
+    pub domain: Option<String>,
+    /// Only blogs with this tag, repeat the parameter for several tags
+    #[serde(default)]
+    pub tag: Vec<String>,
+    /// Whether blogs need `any` of the tags, the default, or `all` of them
+    #[param(inline)]
+    pub tag_match: Option<TagMatch>,
+}
+
+#[derive(Deserialize, ToSchema, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TagMatch {
+    #[default]
+    Any,
+    All,
+}
+
+/// Query parameter ordering a list of blogs
+#[derive(Deserialize, IntoParams, Debug, Default)]
+#[into_params(parameter_in = Query)]
+pub struct BlogSortParams {
+    /// Comma separated fields to sort by, `-` sorts descending.
+    /// One of `id`, `title`, `author`, `likes`, `created_at`, `updated_at`. Ties are broken by id
+    #[param(example = "-likes,title")]
+    pub sort: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortField {
+    Id,
+    Title,
+    Author,
+    Likes,
+    CreatedAt,
+    UpdatedAt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Asc,
+    Desc,
+}
+
+/// Sort keys of a list of blogs, always ending with the id so that the order is total
+#[derive(Debug, Clone)]
+pub struct BlogSort(Vec<(SortField, Direction)>);
+
+impl BlogFilter {
+    /// Appends ` AND <condition>` for every filter that is set
+    pub fn push_conditions(&self, query: &mut QueryBuilder<'_, Postgres>) {
+        if let Some(author) = &self.author {
+            query.push(" AND author = ").push_bind(author.clone());
+        }
+        if let Some(author) = &self.author_contains {
+            query
+                .push(" AND author ILIKE ")
+                .push_bind(contains_pattern(author));
+        }
+        if let Some(title) = &self.title {
+            query
+                .push(" AND title ILIKE ")
+                .push_bind(contains_pattern(title));
+        }
+        if let Some(min_likes) = self.min_likes {
+            query.push(" AND likes >= ").push_bind(min_likes);
+        }
+        if let Some(max_likes) = self.max_likes {
+            query.push(" AND likes <= ").push_bind(max_likes);
+        }
+        if let Some(created_after) = self.created_after {
+            query.push(" AND created_at >= ").push_bind(created_after);
+        }
+        if let Some(created_before) = self.created_before {
+            query.push(" AND created_at < ").push_bind(created_before);
+        }
+        if let Some(updated_after) = self.updated_after {
+            query.push(" AND updated_at >= ").push_bind(updated_after);
+        }
+        if let Some(updated_before) = self.updated_before {
+            query.push(" AND updated_at < ").push_bind(updated_before);
+        }
+        if let Some(domain) = &self.domain {
+            let domain = domain.to_lowercase();
+            let domain = domain.strip_prefix("www.").unwrap_or(&domain);
+            query.push(" AND domain = ").push_bind(domain.to_string());
+        }
+        if !self.tag.is_empty() {
+            let tags = normalize_tags(&self.tag);
+            let required = match self.tag_match.unwrap_or_default() {
+                TagMatch::Any => 1,
+                TagMatch::All => tags.len() as i64,
+            };
+            query
+                .push(
+                    " AND (SELECT COUNT(*) FROM blog_tags JOIN tags ON tags.id = blog_tags.tag_id \
+                    WHERE blog_tags.blog_id = blogs.id AND tags.name = ANY(",
+                )
+                .push_bind(tags)
+                .push(")) >= ")
+                .push_bind(required);
+        }
+    }
+}
+
+impl SortField {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "id" => Some(SortField::Id),
+            "title" => Some(SortField::Title),
+            "author" => Some(SortField::Author),
+            "likes" => Some(SortField::Likes),
+            "created_at" => Some(SortField::CreatedAt),
+            "updated_at" => Some(SortField::UpdatedAt),
+            _ => None,
+        }
+    }
+
+    fn column(self) -> &'static str {
+        match self {
+            SortField::Id => "id",
+            SortField::Title => "title",
+            SortField::Author => "author",
+            SortField::Likes => "likes",
+            SortField::CreatedAt => "created_at",
+            SortField::UpdatedAt => "updated_at",
+        }
+    }
+
+    fn value(self, blog: &Blog) -> Value {
+        match self {
+            SortField::Id => Value::from(blog.id),
+            SortField::Title => Value::from(blog.title.clone()),
+            SortField::Author => Value::from(blog.author.clone()),
+            SortField::Likes => Value::from(blog.likes),
+            SortField::CreatedAt => Value::from(cursor_timestamp(blog.created_at)),
+            SortField::UpdatedAt => Value::from(cursor_timestamp(blog.updated_at)),
+        }
+    }
+
+    /// Binds a value taken from a cursor with the type of this field
+    fn push_bind(
+        self,
+        query: &mut QueryBuilder<'_, Postgres>,
+        value: &Value,
+    ) -> Result<(), AppError> {
+        match self {
+            SortField::Id | SortField::Likes => {
+                let value = value
+                    .as_i64()
+                    .and_then(|v| i32::try_from(v).ok())
+                    .ok_or_else(invalid_cursor)?;
+                query.push_bind(value);
+            }
+            SortField::Title | SortField::Author => {
+                let value = value.as_str().ok_or_else(invalid_cursor)?;
+                query.push_bind(value.to_string());
+            }
+            SortField::CreatedAt | SortField::UpdatedAt => {
+                let value = value
+                    .as_str()
+                    .and_then(|v| DateTime::parse_from_rfc3339(v).ok())
+                    .ok_or_else(invalid_cursor)?;
+                query.push_bind(value.with_timezone(&Utc));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl BlogSortParams {
+    pub fn sort(&self) -> Result<BlogSort, AppError> {
+        let mut keys = vec![];
+        for name in self
+            .sort
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            let (direction, name) = match name.strip_prefix('-') {
+                Some(name) => (Direction::Desc, name),
+                None => (Direction::Asc, name.strip_prefix('+').unwrap_or(name)),
+            };
+            let field = SortField::parse(name).ok_or_else(|| {
+                AppError::invalid_field(
+                    "Invalid query parameter",
+                    "sort",
+                    format!(
+                        "`{name}` is not one of id, title, author, likes, created_at, updated_at"
+                    ),
+                )
+            })?;
+            if keys.iter().any(|(f, _)| *f == field) {
+                continue;
+            }
+            keys.push((field, direction));
+        }
+        if !keys.iter().any(|(field, _)| *field == SortField::Id) {
+            keys.push((SortField::Id, Direction::Asc));
+        }
+
+        Ok(BlogSort(keys))
+    }
+}
+
+impl BlogSort {
+    /// Values of the sort keys of a blog, as stored in cursors
+    pub fn keys(&self, blog: &Blog) -> Vec<Value> {
+        self.0.iter().map(|(field, _)| field.value(blog)).collect()
+    }
+
+    /// Appends the keyset condition of the page, if any, as ` AND (...)`
+    pub fn push_keyset_condition(
+        &self,
+        query: &mut QueryBuilder<'_, Postgres>,
+        page: &Page,
+    ) -> Result<(), AppError> {
+        let (values, backwards) = match &page.mode {
+            PageMode::After(values) => (values, false),
+            PageMode::Before(values) => (values, true),
+            PageMode::Offset(_) | PageMode::Start => return Ok(()),
+        };
+        if values.len() != self.0.len() {
+            return Err(invalid_cursor());
+        }
+
+        // (k1 > v1) OR (k1 = v1 AND k2 > v2) OR ...
+        query.push(" AND (");
+        for i in 0..self.0.len() {
+            if i > 0 {
+                query.push(" OR ");
+            }
+            query.push("(");
+            for (j, ((field, direction), value)) in
+                self.0.iter().zip(values).take(i + 1).enumerate()
+            {
+                if j > 0 {
+                    query.push(" AND ");
+                }
+                let operator = match (j == i, (*direction == Direction::Asc) != backwards) {
+                    (false, _) => " = ",
+                    (true, true) => " > ",
+                    (true, false) => " < ",
+                };
+                query.push(field.column()).push(operator);
+                field.push_bind(query, value)?;
+            }
+            query.push(")");
+        }
+        query.push(")");
+
+        Ok(())
+    }
+
+    /// Appends ` ORDER BY ...`, reversed when the page is fetched backwards
+    pub fn push_order_by(&self, query: &mut QueryBuilder<'_, Postgres>, page: &Page) {
+        query.push(" ORDER BY ");
+        for (i, (field, direction)) in self.0.iter().enumerate() {
+            if i > 0 {
+                query.push(", ");
+            }
+            let ascending = (*direction == Direction::Asc) != page.is_backwards();
+            query
+                .push(field.column())
+                .push(if ascending { " ASC" } else { " DESC" });
+        }
+    }
+}
+
+/// Timestamps keep their full microsecond precision in cursors, so that no row is skipped
+fn cursor_timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+/// ILIKE pattern matching values that contain `value`
+fn contains_pattern(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{escaped}%")
+}
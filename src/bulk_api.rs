@@ -0,0 +1,604 @@
+use axum::{
+    body::Body,
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Extension, Json,
+};
+use futures_util::{stream, StreamExt};
+use serde::Deserialize;
+use sqlx::{types::Json as SqlJson, PgPool};
+use tokio::sync::mpsc;
+use tracing::error;
+use utoipa::{IntoParams, ToSchema};
+use validator::Validate;
+
+use crate::{
+    auth::AuthUser,
+    errors::{AppError, FieldErrors, ProblemDetails},
+    extractors::{deserialize_json_value, AppQuery, ImportBody, NDJSON_CONTENT_TYPE},
+    models::{normalize_tags, Blog, BlogPostPayload, BlogUser, ImportReport},
+};
+
+/// Most rows a single import may contain
+pub const MAX_IMPORT_ROWS: usize = 1000;
+
+/// Rows buffered between the database and a slow client during an export
+const EXPORT_BUFFER_ROWS: usize = 64;
+
+const CSV_COLUMNS: &[&str] = &[
+    "id",
+    "title",
+    "author",
+    "url",
+    "tags",
+    "likes",
+    "comment_count",
+    "created_at",
+    "updated_at",
+    "username",
+];
+
+#[derive(Deserialize, IntoParams, Debug, Default)]
+#[into_params(parameter_in = Query)]
+pub struct ImportParams {
+    /// Only validate the rows, nothing is written
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Deserialize, ToSchema, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Ndjson,
+    Csv,
+}
+
+#[derive(Deserialize, IntoParams, Debug, Default)]
+#[into_params(parameter_in = Query)]
+pub struct ExportParams {
+    /// Format of the export, JSON array by default
+    #[param(inline)]
+    pub format: Option<ExportFormat>,
+}
+
+/// Import blogs
+///
+/// Adds many blogs at once on behalf of the logged in user, from a JSON array, newline
+/// delimited JSON or CSV with a `title,author,url[,tags]` header. Every row is validated like
+/// a `POST /blogs` body and either all rows are imported, in a single transaction, or none.
+/// Problems are reported per row in `errors`, keyed by `[row].field` with rows counted from 0
+/// and without the CSV header. Rows with the url of an existing blog or of an earlier row are
+/// invalid too. With `dry_run` the rows are only validated
+#[utoipa::path(
+    post,
+    path = "/blogs/import",
+    params(ImportParams),
+    request_body(content(
+        (Vec<BlogPostPayload> = "application/json"),
+        (BlogPostPayload = "application/x-ndjson"),
+        (String = "text/csv")
+    )),
+    security(("bearer" = [])),
+    responses(
+        (status = 500, description = "Internal server error", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 400, description = "Malformed upload or invalid rows", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 401, description = "Missing, expired or invalid token", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 415, description = "Unsupported upload format", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 200, description = "Rows are valid, nothing was imported on this dry run", body=ImportReport),
+        (status = 201, description = "Blogs imported successfully", body=ImportReport)
+    )
+)]
+pub async fn import_blogs(
+    Extension(pool): Extension<PgPool>,
+    user: AuthUser,
+    AppQuery(params): AppQuery<ImportParams>,
+    ImportBody(rows): ImportBody,
+) -> Result<impl IntoResponse, AppError> {
+    if rows.len() > MAX_IMPORT_ROWS {
+        return Err(AppError::BadRequest(format!(
+            "An import can contain at most {MAX_IMPORT_ROWS} rows, got {}",
+            rows.len()
+        )));
+    }
+
+    let mut blogs = Vec::with_capacity(rows.len());
+    let mut errors = FieldErrors::new();
+    for (i, row) in rows.into_iter().enumerate() {
+        match row.map_err(AppError::BadRequest).and_then(parse_row) {
+            Ok(blog) => blogs.push(blog),
+            Err(AppError::InvalidFields(_, fields)) => {
+                errors.extend(
+                    fields
+                        .into_iter()
+                        .map(|(field, messages)| (format!("[{i}].{field}"), messages)),
+                );
+            }
+            Err(AppError::BadRequest(detail)) => {
+                errors.insert(format!("[{i}]"), vec![detail]);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    if errors.is_empty() {
+        errors = duplicate_urls(&pool, &blogs).await?;
+    }
+    if !errors.is_empty() {
+        return Err(AppError::InvalidFields(
+            format!(
+                "{} of the rows are invalid, nothing was imported",
+                errors_rows(&errors)
+            ),
+            errors,
+        ));
+    }
+
+    let rows = blogs.len();
+    if params.dry_run {
+        let report = ImportReport {
+            dry_run: true,
+            rows,
+            ids: vec![],
+        };
+        return Ok((StatusCode::OK, Json(report)));
+    }
+
+    let mut tx = pool.begin().await?;
+    let (titles, authors, urls): (Vec<String>, Vec<String>, Vec<String>) = blogs
+        .iter()
+        .map(|blog| (blog.title.clone(), blog.author.clone(), blog.url.clone()))
+        .collect();
+    // ids are handed out in row order, so sorting them gives back the order of the rows
+    let ids = sqlx::query_scalar!(
+        r#"WITH rows AS (
+            SELECT * FROM UNNEST($1::text[], $2::text[], $3::text[]) WITH ORDINALITY AS t(title, author, url, n)
+        ), inserted AS (
+            INSERT INTO blogs (title, author, url, user_id)
+            SELECT title, author, url, $4 FROM rows ORDER BY n
+            RETURNING id
+        )
+        SELECT id AS "id!" FROM inserted ORDER BY id"#,
+        &titles[..],
+        &authors[..],
+        &urls[..],
+        user.id
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let (tag_blogs, tag_names): (Vec<i32>, Vec<String>) = ids
+        .iter()
+        .zip(&blogs)
+        .flat_map(|(id, blog)| normalize_tags(&blog.tags).into_iter().map(|tag| (*id, tag)))
+        .unzip();
+    sqlx::query!(
+        "INSERT INTO tags (name) SELECT DISTINCT unnest($1::text[]) ON CONFLICT (name) DO NOTHING",
+        &tag_names[..]
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "INSERT INTO blog_tags (blog_id, tag_id)
+        SELECT t.blog_id, tags.id FROM UNNEST($1::int[], $2::text[]) AS t(blog_id, name)
+        JOIN tags ON tags.name = t.name",
+        &tag_blogs[..],
+        &tag_names[..]
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "INSERT INTO blog_revisions (blog_id, rev, action, user_id, new_values)
+        SELECT id, 1, 'create', $2, blog_values(id) FROM UNNEST($1::int[]) AS id",
+        &ids[..],
+        user.id
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    let report = ImportReport {
+        dry_run: false,
+        rows,
+        ids,
+    };
+    Ok((StatusCode::CREATED, Json(report)))
+}
+
+/// Export all blogs
+///
+/// Streams every blog, by id, as a JSON array, newline delimited JSON or CSV. Rows are sent
+/// while they are read from the database, so exports of any size use little memory. The CSV
+/// can be imported again with `POST /blogs/import`
+#[utoipa::path(
+    get,
+    path = "/blogs/export",
+    params(ExportParams),
+    responses(
+        (status = 500, description = "Internal server error", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 400, description = "Unknown format", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 200, description = "Blogs exported successfully", content(
+            ([Blog] = "application/json"),
+            (Blog = "application/x-ndjson"),
+            (String = "text/csv")
+        ))
+    )
+)]
+pub async fn export_blogs(
+    Extension(pool): Extension<PgPool>,
+    AppQuery(params): AppQuery<ExportParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let format = params.format.unwrap_or_default();
+    let (content_type, extension) = match format {
+        ExportFormat::Json => ("application/json", "json"),
+        ExportFormat::Ndjson => (NDJSON_CONTENT_TYPE, "ndjson"),
+        ExportFormat::Csv => ("text/csv; charset=utf-8", "csv"),
+    };
+
+    // the query borrows the pool, it runs in its own task that feeds the response body
+    let (sender, receiver) = mpsc::channel::<Result<String, sqlx::Error>>(EXPORT_BUFFER_ROWS);
+    tokio::spawn(async move {
+        let mut rows = sqlx::query_as!(
+            Blog,
+            r#"SELECT id, title, author, url, blog_tag_names(id) AS "tags!", likes, comment_count, created_at, updated_at, version, blog_user(user_id) AS "user: SqlJson<BlogUser>" FROM blogs WHERE deleted_at IS NULL ORDER BY id"#
+        )
+        .fetch(&pool);
+
+        let header = match format {
+            ExportFormat::Json => "[".to_string(),
+            ExportFormat::Ndjson => String::new(),
+            ExportFormat::Csv => csv_line(CSV_COLUMNS.iter().copied()),
+        };
+        if sender.send(Ok(header)).await.is_err() {
+            return;
+        }
+        let mut first = true;
+        while let Some(row) = rows.next().await {
+            let chunk = row.map(|blog| encode_blog(format, &blog, first));
+            first = false;
+            let failed = chunk.is_err();
+            if let Err(e) = &chunk {
+                error!("Export failed: {e}");
+            }
+            // stop when the client is gone, a failed query aborts the response
+            if sender.send(chunk).await.is_err() || failed {
+                return;
+            }
+        }
+        if format == ExportFormat::Json {
+            let _ = sender.send(Ok("]".to_string())).await;
+        }
+    });
+
+    let body = stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    });
+    let disposition = format!("attachment; filename=\"blogs.{extension}\"");
+
+    Ok((
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        Body::from_stream(body),
+    ))
+}
+
+/// A row of an import turned into a valid blog
+fn parse_row(row: serde_json::Value) -> Result<BlogPostPayload, AppError> {
+    let blog: BlogPostPayload = deserialize_json_value(row)?;
+    blog.validate()?;
+    Ok(blog)
+}
+
+/// Per-row errors of the rows whose url was already submitted, as a blog or in an earlier row
+async fn duplicate_urls(pool: &PgPool, blogs: &[BlogPostPayload]) -> Result<FieldErrors, AppError> {
+    let urls: Vec<String> = blogs.iter().map(|blog| blog.url.clone()).collect();
+    let duplicates = sqlx::query!(
+        r#"SELECT n - 1 AS "row!", existing, first - 1 AS "first!"
+        FROM (
+            SELECT n,
+                (SELECT id FROM blogs
+                WHERE blogs.canonical_url = canonical_url(t.url) AND deleted_at IS NULL) AS existing,
+                MIN(n) OVER (PARTITION BY canonical_url(t.url)) AS first
+            FROM UNNEST($1::text[]) WITH ORDINALITY AS t(url, n)
+        ) AS rows
+        WHERE existing IS NOT NULL OR first < n"#,
+        &urls[..]
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(duplicates
+        .into_iter()
+        .map(|duplicate| {
+            let message = match duplicate.existing {
+                Some(id) => format!("already submitted as the blog with id={id}"),
+                None => format!("same as the url of row {}", duplicate.first),
+            };
+            (format!("[{}].url", duplicate.row), vec![message])
+        })
+        .collect())
+}
+
+/// Number of rows mentioned in the per-row errors
+fn errors_rows(errors: &FieldErrors) -> usize {
+    let mut rows: Vec<&str> = errors
+        .keys()
+        .filter_map(|key| key.split(']').next())
+        .collect();
+    rows.dedup();
+    rows.len()
+}
+
+fn encode_blog(format: ExportFormat, blog: &Blog, first: bool) -> String {
+    match format {
+        ExportFormat::Json => {
+            let separator = if first { "" } else { "," };
+            format!(
+                "{separator}{}",
+                serde_json::to_string(blog).unwrap_or_default()
+            )
+        }
+        ExportFormat::Ndjson => format!("{}\n", serde_json::to_string(blog).unwrap_or_default()),
+        ExportFormat::Csv => csv_line(
+            [
+                blog.id.to_string(),
+                blog.title.clone(),
+                blog.author.clone(),
+                blog.url.clone(),
+                blog.tags.join(" "),
+                blog.likes.to_string(),
+                blog.comment_count.to_string(),
+                blog.created_at.to_rfc3339(),
+                blog.updated_at.to_rfc3339(),
+                blog.user
+                    .as_ref()
+                    .map(|user| user.username.clone())
+                    .unwrap_or_default(),
+            ]
+            .iter()
+            .map(String::as_str),
+        ),
+    }
+}
+
+/// One CSV record, quoted where needed, with its line break
+fn csv_line<'a>(fields: impl IntoIterator<Item = &'a str>) -> String {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    let line = writer
+        .write_record(fields)
+        .ok()
+        .and_then(|_| writer.into_inner().ok())
+        .unwrap_or_default();
+    String::from_utf8(line).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod bulk_api_test {
+    use crate::test_helper::{get_test_blogs, insert_test_values, setup_test_context};
+    use axum::http::{header, StatusCode};
+    use rstest::*;
+    use serde_json::{json, Value};
+
+    const JSON_ROWS: &str = r#"[
+        {"title": "React patterns", "author": "Michael Chan", "url": "https://reactpatterns.com/", "tags": ["React"]},
+        {"title": "Type wars", "author": "Robert C. Martin", "url": "http://blog.cleancoder.com/uncle-bob/2016/05/01/TypeWars.html"},
+        {"title": "TDD harms architecture", "author": "Robert C. Martin", "url": "http://blog.cleancoder.com/", "tags": ["tdd", "architecture"]}
+    ]"#;
+    const NDJSON_ROWS: &str = r#"{"title": "React patterns", "author": "Michael Chan", "url": "https://reactpatterns.com/", "tags": ["React"]}
+{"title": "Type wars", "author": "Robert C. Martin", "url": "http://blog.cleancoder.com/uncle-bob/2016/05/01/TypeWars.html"}
+
+{"title": "TDD harms architecture", "author": "Robert C. Martin", "url": "http://blog.cleancoder.com/", "tags": ["tdd", "architecture"]}
+"#;
+    const CSV_ROWS: &str = "title,author,url,tags
+React patterns,Michael Chan,https://reactpatterns.com/,React
+Type wars,\"Robert C. Martin\",http://blog.cleancoder.com/uncle-bob/2016/05/01/TypeWars.html,
+TDD harms architecture,Robert C. Martin,http://blog.cleancoder.com/,\"tdd, architecture\"
+";
+
+    // every upload format imports the same blogs, in order, owned by the importer
+    #[rstest]
+    #[case::json("application/json", JSON_ROWS)]
+    #[case::ndjson("application/x-ndjson", NDJSON_ROWS)]
+    #[case::csv("text/csv", CSV_ROWS)]
+    #[tokio::test]
+    async fn import_blogs(#[case] content_type: &str, #[case] body: &str) {
+        let mut ctx = setup_test_context().await;
+        ctx.login_as("root").await;
+
+        let response = ctx
+            .server
+            .post("/blogs/import")
+            .bytes(body.to_string().into())
+            .content_type(content_type)
+            .await;
+
+        response.assert_status(StatusCode::CREATED);
+        response.assert_json(&json!({"dry_run": false, "rows": 3, "ids": [1, 2, 3]}));
+        let blogs: Vec<Value> = ctx.server.get("/blogs").await.json();
+        let summary: Vec<(Value, Value)> = blogs
+            .iter()
+            .map(|blog| (blog["title"].clone(), blog["tags"].clone()))
+            .collect();
+        assert_eq!(
+            vec![
+                (json!("React patterns"), json!(["react"])),
+                (json!("Type wars"), json!([])),
+                (
+                    json!("TDD harms architecture"),
+                    json!(["architecture", "tdd"])
+                ),
+            ],
+            summary
+        );
+        assert_eq!("root", blogs[1]["user"]["username"]);
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // every invalid row is reported and nothing is imported, dry runs never write
+    #[rstest]
+    #[case::invalid_rows(false, "application/json", r#"[
+        {"title": "ok", "author": "a", "url": "http://a.com"},
+        {"title": "", "author": "a", "url": "ftp://a.com"},
+        {"title": "ok", "author": "a", "url": "http://a.com", "tags": ["no spaces"]},
+        {"author": "a", "url": "http://a.com"}
+    ]"#, json!(["[1].title", "[1].url", "[2].tags", "[3].title"]))]
+    #[case::malformed_line(false, "application/x-ndjson", "{\"title\": \"ok\", \"author\": \"a\", \"url\": \"http://a.com\"}\n{\"title\":", json!(["[1]"]))]
+    #[case::short_csv_row(false, "text/csv", "title,author,url\nok,a,http://a.com\nok,a\n", json!(["[1]"]))]
+    #[case::dry_run_invalid(true, "text/csv", "title,author\nok,a\n", json!(["[0].url"]))]
+    #[tokio::test]
+    async fn import_errors(
+        #[case] dry_run: bool,
+        #[case] content_type: &str,
+        #[case] body: &str,
+        #[case] expected_fields: Value,
+    ) {
+        let mut ctx = setup_test_context().await;
+        ctx.login_as("root").await;
+
+        let response = ctx
+            .server
+            .post(&format!("/blogs/import?dry_run={dry_run}"))
+            .bytes(body.to_string().into())
+            .content_type(content_type)
+            .await;
+
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let problem: Value = response.json();
+        let fields: Vec<&String> = problem["errors"].as_object().unwrap().keys().collect();
+        assert_eq!(expected_fields, json!(fields));
+        ctx.server
+            .get("/blogs")
+            .await
+            .assert_header("x-total-count", "0");
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // a valid dry run reports the rows without importing them
+    #[rstest]
+    #[tokio::test]
+    async fn import_dry_run() {
+        let mut ctx = setup_test_context().await;
+        ctx.login_as("root").await;
+
+        let response = ctx
+            .server
+            .post("/blogs/import?dry_run=true")
+            .bytes(JSON_ROWS.into())
+            .content_type("application/json")
+            .await;
+
+        response.assert_status(StatusCode::OK);
+        response.assert_json(&json!({"dry_run": true, "rows": 3, "ids": []}));
+        ctx.server
+            .get("/blogs")
+            .await
+            .assert_header("x-total-count", "0");
+        ctx.server.get("/tags").await.assert_json(&json!([]));
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // imports need a user and a supported format
+    #[rstest]
+    #[tokio::test]
+    async fn import_rejections() {
+        let mut ctx = setup_test_context().await;
+
+        ctx.server
+            .post("/blogs/import")
+            .bytes(JSON_ROWS.into())
+            .content_type("application/json")
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+
+        ctx.login_as("root").await;
+        ctx.server
+            .post("/blogs/import")
+            .bytes(JSON_ROWS.into())
+            .content_type("application/xml")
+            .await
+            .assert_status(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        ctx.server
+            .post("/blogs/import")
+            .json(&json!({"title": "not", "author": "an", "url": "http://array.com"}))
+            .await
+            .assert_status(StatusCode::BAD_REQUEST);
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // exports contain every blog in each format, a CSV export can be imported again
+    #[rstest]
+    #[tokio::test]
+    async fn export_blogs() {
+        let mut ctx = setup_test_context().await;
+        insert_test_values(&ctx.pool)
+            .await
+            .expect("Expected insert statement to work");
+
+        let response = ctx.server.get("/blogs/export").await;
+        response.assert_status(StatusCode::OK);
+        response.assert_header(header::CONTENT_TYPE, "application/json");
+        response.assert_json(&json!(get_test_blogs()));
+
+        let response = ctx.server.get("/blogs/export?format=ndjson").await;
+        response.assert_header(
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"blogs.ndjson\"",
+        );
+        let blogs: Vec<Value> = response
+            .text()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(json!(get_test_blogs()), json!(blogs));
+
+        let response = ctx.server.get("/blogs/export?format=csv").await;
+        let csv = response.text();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(7, lines.len());
+        assert_eq!(
+            "id,title,author,url,tags,likes,comment_count,created_at,updated_at,username",
+            lines[0]
+        );
+        assert!(
+            lines[1].starts_with("1,React patterns,Michael Chan,https://reactpatterns.com/,,7,0,")
+        );
+
+        ctx.server
+            .get("/blogs/export?format=xml")
+            .await
+            .assert_status(StatusCode::BAD_REQUEST);
+
+        // the export can be imported again once the exported blogs are gone
+        sqlx::query("DELETE FROM blogs")
+            .execute(&ctx.pool)
+            .await
+            .unwrap();
+        ctx.login_as("root").await;
+        let response = ctx
+            .server
+            .post("/blogs/import")
+            .bytes(csv.into())
+            .content_type("text/csv")
+            .await;
+        response.assert_status(StatusCode::CREATED);
+        assert_eq!(6, response.json::<Value>()["rows"]);
+        ctx.server
+            .get("/blogs")
+            .await
+            .assert_header("x-total-count", "6");
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+}
@@ -0,0 +1,495 @@
+use axum::{
+    http::{StatusCode, Uri},
+    response::IntoResponse,
+    Extension, Json,
+};
+use serde_json::Value;
+use sqlx::{error::ErrorKind, types::Json as SqlJson, PgPool, QueryBuilder};
+
+use crate::{
+    auth::{AuthUser, CommentAuthor},
+    blogs_api::blog_not_found,
+    errors::{AppError, ProblemDetails},
+    extractors::{AppPath, AppQuery, ValidatedJson},
+    models::{BlogUser, Comment, CommentPostPayload, CommentUpdatePayload},
+    pagination::{invalid_cursor, PageMode, PageParams},
+};
+
+/// Get the comments of a blog
+///
+/// Returns a page of the comments of a blog, replies included, oldest first. Replies point to
+/// the comment they answer with `parent_id`, so that clients can rebuild the threads. Pages are
+/// selected either with `offset` or with the opaque `cursor` found in the `Link` header. The
+/// total number of comments is returned in `X-Total-Count`
+#[utoipa::path(
+    get,
+    path = "/blogs/{id}/comments",
+    params(("id" = i32, Path, description = "Blog id"), PageParams),
+    responses(
+        (status = 500, description = "Internal server error", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 404, description = "Blog not found", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 400, description = "Invalid blog id or paging parameters", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 200, description = "Comments retrieved successfully", body=[Comment],
+            headers(
+                ("X-Total-Count" = i64, description = "Total number of comments of the blog"),
+                ("Link" = String, description = "Links to the `prev` and `next` pages")
+            )
+        )
+    )
+)]
+pub async fn get_comments(
+    Extension(pool): Extension<PgPool>,
+    uri: Uri,
+    AppPath(blog_id): AppPath<i32>,
+    AppQuery(params): AppQuery<PageParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let page = params.page()?;
+    let total = sqlx::query_scalar!(
+        "SELECT comment_count FROM blogs WHERE id = $1 AND deleted_at IS NULL",
+        blog_id
+    )
+    .fetch_optional(&pool)
+    .await?
+    .ok_or_else(|| blog_not_found(blog_id))?;
+
+    let mut query = QueryBuilder::new(
+        r#"SELECT id, blog_id, parent_id, body, created_at, updated_at, blog_user(user_id) AS "user" FROM comments WHERE blog_id = "#,
+    );
+    query.push_bind(blog_id);
+    match &page.mode {
+        PageMode::After(keys) | PageMode::Before(keys) => {
+            let [Value::Number(id)] = keys.as_slice() else {
+                return Err(invalid_cursor());
+            };
+            let id = id.as_i64().ok_or_else(invalid_cursor)?;
+            query
+                .push(if page.is_backwards() {
+                    " AND id < "
+                } else {
+                    " AND id > "
+                })
+                .push_bind(id);
+        }
+        PageMode::Offset(_) | PageMode::Start => {}
+    }
+    query.push(if page.is_backwards() {
+        " ORDER BY id DESC"
+    } else {
+        " ORDER BY id ASC"
+    });
+    query.push(" LIMIT ").push_bind(page.fetch_limit());
+    if let PageMode::Offset(offset) = page.mode {
+        query.push(" OFFSET ").push_bind(offset);
+    }
+    let rows: Vec<Comment> = query.build_query_as().fetch_all(&pool).await?;
+
+    let result = page.finish(rows, |comment| vec![Value::from(comment.id)]);
+    let headers = result.headers(&uri, page.limit, total.into());
+
+    Ok((StatusCode::OK, headers, Json(result.items)))
+}
+
+/// Comment on a blog
+///
+/// Adds a comment of the logged in user to a blog. With `parent_id` the comment is a reply to
+/// another comment of the same blog. Returns the created comment
+#[utoipa::path(
+    post,
+    path = "/blogs/{id}/comments",
+    params(
+        ("id" = i32, Path, description = "Blog id"),
+        ("Idempotency-Key" = Option<String>, Header, description = "Makes retries return the first response instead of running again")
+    ),
+    request_body = CommentPostPayload,
+    security(("bearer" = [])),
+    responses(
+        (status = 500, description = "Internal server error", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 404, description = "Blog not found", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 400, description = "Invalid blog id, malformed or invalid request body", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 401, description = "Missing, expired or invalid token", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 415, description = "Request body is not JSON", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 422, description = "Parent comment is not a comment of the blog", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 201, description = "Comment created successfully", body=Comment)
+    )
+)]
+pub async fn create_comment(
+    Extension(pool): Extension<PgPool>,
+    user: AuthUser,
+    AppPath(blog_id): AppPath<i32>,
+    ValidatedJson(body): ValidatedJson<CommentPostPayload>,
+) -> Result<impl IntoResponse, AppError> {
+    let comment = sqlx::query_as!(
+        Comment,
+        r#"INSERT INTO comments (blog_id, parent_id, user_id, body)
+        SELECT $1, $2, $3, $4 WHERE EXISTS (SELECT 1 FROM blogs WHERE id = $1 AND deleted_at IS NULL)
+        RETURNING id, blog_id, parent_id, body, created_at, updated_at, blog_user(user_id) AS "user!: SqlJson<BlogUser>""#,
+        blog_id,
+        body.parent_id,
+        user.id,
+        body.body.trim()
+    )
+    .fetch_optional(&pool)
+    .await
+    .map_err(|e| match &e {
+        sqlx::Error::Database(db_err) if db_err.kind() == ErrorKind::ForeignKeyViolation => {
+            match db_err.constraint() {
+                Some("comments_blog_id_fkey") => blog_not_found(blog_id),
+                Some("comments_parent_fkey") => AppError::Unprocessable(format!(
+                    "Comment with id={} is not a comment of the blog with id={blog_id}",
+                    body.parent_id.unwrap_or_default()
+                )),
+                _ => AppError::from(e),
+            }
+        }
+        _ => AppError::from(e),
+    })?
+    .ok_or_else(|| blog_not_found(blog_id))?;
+
+    Ok((StatusCode::CREATED, Json(comment)))
+}
+
+/// Edit a comment
+///
+/// Replaces the text of a comment given the id. Only the user who wrote the comment or an
+/// admin may edit it
+#[utoipa::path(
+    patch,
+    path = "/comments/{id}",
+    params(("id" = i32, Path, description = "Comment id")),
+    request_body(content = CommentUpdatePayload, content_type = "application/merge-patch+json"),
+    security(("bearer" = [])),
+    responses(
+        (status = 500, description = "Internal server error", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 404, description = "Comment not found", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 400, description = "Invalid comment id, malformed or invalid request body", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 401, description = "Missing, expired or invalid token", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 403, description = "Comment was written by another user", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 415, description = "Request body is not JSON", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 200, description = "Comment updated successfully", body=Comment)
+    )
+)]
+pub async fn update_comment(
+    Extension(pool): Extension<PgPool>,
+    CommentAuthor(id): CommentAuthor,
+    ValidatedJson(body): ValidatedJson<CommentUpdatePayload>,
+) -> Result<impl IntoResponse, AppError> {
+    let comment = sqlx::query_as!(
+        Comment,
+        r#"UPDATE comments SET body = $1 WHERE id = $2
+        RETURNING id, blog_id, parent_id, body, created_at, updated_at, blog_user(user_id) AS "user!: SqlJson<BlogUser>""#,
+        body.body.trim(),
+        id
+    )
+    .fetch_optional(&pool)
+    .await?
+    .ok_or_else(|| comment_not_found(id))?;
+
+    Ok((StatusCode::OK, Json(comment)))
+}
+
+/// Delete a comment
+///
+/// Deletes a comment and the replies to it given the id. Only the user who wrote the comment
+/// or an admin may delete it
+#[utoipa::path(
+    delete,
+    path = "/comments/{id}",
+    params(("id" = i32, Path, description = "Comment id")),
+    security(("bearer" = [])),
+    responses(
+        (status = 500, description = "Failed to delete comment", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 404, description = "Comment not found", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 400, description = "Invalid comment id", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 401, description = "Missing, expired or invalid token", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 403, description = "Comment was written by another user", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 204, description = "Comment deleted successfully")
+    )
+)]
+pub async fn delete_comment(
+    Extension(pool): Extension<PgPool>,
+    CommentAuthor(id): CommentAuthor,
+) -> Result<impl IntoResponse, AppError> {
+    let result = sqlx::query!("DELETE FROM comments WHERE id = $1", id)
+        .execute(&pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(comment_not_found(id));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn comment_not_found(id: i32) -> AppError {
+    AppError::NotFound(format!("Comment with id={} not found", id))
+}
+
+#[cfg(test)]
+mod comments_api_test {
+    use crate::test_helper::{insert_test_values, setup_test_context};
+    use axum::http::StatusCode;
+    use rstest::*;
+    use serde_json::{json, Value};
+
+    // comments and replies are listed oldest first and counted on their blog
+    #[rstest]
+    #[tokio::test]
+    async fn comment_thread() {
+        let mut ctx = setup_test_context().await;
+        insert_test_values(&ctx.pool)
+            .await
+            .expect("Expected insert statement to work");
+        ctx.login_as("root").await;
+
+        let response = ctx
+            .server
+            .post("/blogs/1/comments")
+            .json(&json!({"body": "  Great read  "}))
+            .await;
+        response.assert_status(StatusCode::CREATED);
+        let comment: Value = response.json();
+        assert_eq!("Great read", comment["body"]);
+        assert_eq!(Value::Null, comment["parent_id"]);
+        assert_eq!(
+            json!({"id": 1, "username": "root", "name": "root"}),
+            comment["user"]
+        );
+
+        let response = ctx
+            .server
+            .post("/blogs/1/comments")
+            .json(&json!({"body": "Agreed", "parent_id": comment["id"]}))
+            .await;
+        response.assert_status(StatusCode::CREATED);
+        let reply: Value = response.json();
+        assert_eq!(comment["id"], reply["parent_id"]);
+
+        let response = ctx.server.get("/blogs/1/comments").await;
+        response.assert_status(StatusCode::OK);
+        response.assert_json(&json!([comment, reply]));
+        response.assert_header("x-total-count", "2");
+        assert_eq!(
+            2,
+            ctx.server.get("/blogs/1").await.json::<Value>()["comment_count"]
+        );
+        assert_eq!(
+            0,
+            ctx.server.get("/blogs/2").await.json::<Value>()["comment_count"]
+        );
+        ctx.server
+            .get("/blogs/2/comments")
+            .await
+            .assert_json(&json!([]));
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // invalid comments are rejected and nothing is written
+    #[rstest]
+    #[case::blank_body("/blogs/1/comments", json!({"body": " "}), StatusCode::BAD_REQUEST)]
+    #[case::missing_body("/blogs/1/comments", json!({}), StatusCode::BAD_REQUEST)]
+    #[case::unknown_blog("/blogs/42/comments", json!({"body": "Hi"}), StatusCode::NOT_FOUND)]
+    #[case::unknown_parent("/blogs/1/comments", json!({"body": "Hi", "parent_id": 42}), StatusCode::UNPROCESSABLE_ENTITY)]
+    #[case::parent_on_other_blog("/blogs/2/comments", json!({"body": "Hi", "parent_id": 1}), StatusCode::UNPROCESSABLE_ENTITY)]
+    #[tokio::test]
+    async fn create_comment_errors(
+        #[case] path: &str,
+        #[case] body: Value,
+        #[case] expected_status_code: StatusCode,
+    ) {
+        let mut ctx = setup_test_context().await;
+        insert_test_values(&ctx.pool)
+            .await
+            .expect("Expected insert statement to work");
+        ctx.login_as("root").await;
+        ctx.server
+            .post("/blogs/1/comments")
+            .json(&json!({"body": "First"}))
+            .await
+            .assert_status(StatusCode::CREATED);
+
+        let response = ctx.server.post(path).json(&body).await;
+
+        response.assert_status(expected_status_code);
+        ctx.server
+            .get("/blogs/1/comments")
+            .await
+            .assert_header("x-total-count", "1");
+        ctx.server
+            .get("/blogs/2/comments")
+            .await
+            .assert_header("x-total-count", "0");
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // comments are paged like blogs, by cursor or by offset
+    #[rstest]
+    #[tokio::test]
+    async fn comments_pagination() {
+        let mut ctx = setup_test_context().await;
+        insert_test_values(&ctx.pool)
+            .await
+            .expect("Expected insert statement to work");
+        ctx.login_as("root").await;
+        for body in ["a", "b", "c"] {
+            ctx.server
+                .post("/blogs/1/comments")
+                .json(&json!({"body": body}))
+                .await
+                .assert_status(StatusCode::CREATED);
+        }
+        let bodies = |response: &axum_test::TestResponse| -> Vec<String> {
+            response
+                .json::<Vec<Value>>()
+                .iter()
+                .map(|comment| comment["body"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        let first = ctx.server.get("/blogs/1/comments?limit=2").await;
+        assert_eq!(vec!["a", "b"], bodies(&first));
+        let link = first.header("link");
+        let next = link.to_str().unwrap().split(['<', '>']).nth(1).unwrap();
+        let second = ctx.server.get(next).await;
+        assert_eq!(vec!["c"], bodies(&second));
+        assert!(second
+            .header("link")
+            .to_str()
+            .unwrap()
+            .contains("rel=\"prev\""));
+
+        let response = ctx.server.get("/blogs/1/comments?limit=2&offset=1").await;
+        assert_eq!(vec!["b", "c"], bodies(&response));
+        ctx.server
+            .get("/blogs/1/comments?cursor=abc")
+            .await
+            .assert_status(StatusCode::BAD_REQUEST);
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // only the author of a comment or an admin can edit or delete it, replies go with it
+    #[rstest]
+    #[tokio::test]
+    async fn only_author_or_admin_changes_comment() {
+        let mut ctx = setup_test_context().await;
+        insert_test_values(&ctx.pool)
+            .await
+            .expect("Expected insert statement to work");
+        ctx.login_as("root").await;
+        let comment: Value = ctx
+            .server
+            .post("/blogs/1/comments")
+            .json(&json!({"body": "First"}))
+            .await
+            .json();
+        ctx.server
+            .post("/blogs/1/comments")
+            .json(&json!({"body": "Reply", "parent_id": 1}))
+            .await
+            .assert_status(StatusCode::CREATED);
+
+        let response = ctx
+            .server
+            .patch("/comments/1")
+            .json(&json!({"body": "Edited"}))
+            .await;
+        response.assert_status(StatusCode::OK);
+        let edited: Value = response.json();
+        assert_eq!("Edited", edited["body"]);
+        assert_eq!(comment["created_at"], edited["created_at"]);
+        assert_ne!(comment["updated_at"], edited["updated_at"]);
+
+        ctx.login_as("mluukkai").await;
+        ctx.server
+            .patch("/comments/1")
+            .json(&json!({"body": "Hijacked"}))
+            .await
+            .assert_status(StatusCode::FORBIDDEN);
+        ctx.server
+            .delete("/comments/1")
+            .await
+            .assert_status(StatusCode::FORBIDDEN);
+        ctx.server
+            .delete("/comments/42")
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+
+        ctx.login_as_admin("admin").await;
+        ctx.server
+            .delete("/comments/1")
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        ctx.server
+            .get("/blogs/1/comments")
+            .await
+            .assert_json(&json!([]));
+        assert_eq!(
+            0,
+            ctx.server.get("/blogs/1").await.json::<Value>()["comment_count"]
+        );
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // purging a deleted blog deletes its comments
+    #[rstest]
+    #[tokio::test]
+    async fn purge_blog_deletes_comments() {
+        let mut ctx = setup_test_context().await;
+        insert_test_values(&ctx.pool)
+            .await
+            .expect("Expected insert statement to work");
+        ctx.login_as_admin("root").await;
+        for blog in [
+            "/blogs/1/comments",
+            "/blogs/1/comments",
+            "/blogs/2/comments",
+        ] {
+            ctx.server
+                .post(blog)
+                .json(&json!({"body": "Hi"}))
+                .await
+                .assert_status(StatusCode::CREATED);
+        }
+
+        ctx.server
+            .delete("/blogs/1")
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+
+        ctx.server
+            .get("/blogs/1/comments")
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+
+        // the comments stay in the trash with the blog until it is purged
+        let count = "SELECT COUNT(*) FROM comments";
+        let comments: i64 = sqlx::query_scalar(count)
+            .fetch_one(&ctx.pool)
+            .await
+            .unwrap();
+        assert_eq!(3, comments);
+        sqlx::query("UPDATE blogs SET deleted_at = now() - INTERVAL '1 year' WHERE id = 1")
+            .execute(&ctx.pool)
+            .await
+            .unwrap();
+        ctx.server
+            .post("/trash/purge")
+            .await
+            .assert_status(StatusCode::OK);
+        let blog_ids: Vec<i32> = sqlx::query_scalar("SELECT blog_id FROM comments")
+            .fetch_all(&ctx.pool)
+            .await
+            .unwrap();
+        assert_eq!(vec![2], blog_ids);
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+}
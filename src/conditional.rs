@@ -0,0 +1,121 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use axum::{
+    extract::FromRequestParts,
+    http::{header, request::Parts, HeaderMap, HeaderValue},
+};
+use chrono::{DateTime, Utc};
+
+use crate::{config::PreconditionConfig, errors::AppError};
+
+/// Entity tag of a blog, changes with its version
+pub fn version_etag(version: i32) -> String {
+    format!("\"{version}\"")
+}
+
+/// Entity tag of a representation that has no version, changes with its content
+pub fn hash_etag(value: impl Hash) -> String {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    format!("\"{:016x}\"", hasher.finish())
+}
+
+/// `ETag` and `Last-Modified` headers for a representation
+pub fn validator_headers(etag: &str, last_modified: Option<DateTime<Utc>>) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if let Ok(value) = HeaderValue::from_str(etag) {
+        headers.insert(header::ETAG, value);
+    }
+    if let Some(last_modified) = last_modified {
+        if let Ok(value) = HeaderValue::from_str(&http_date(last_modified)) {
+            headers.insert(header::LAST_MODIFIED, value);
+        }
+    }
+    headers
+}
+
+/// Whether the client already has the representation, so 304 can be sent instead
+///
+/// `If-None-Match` wins over `If-Modified-Since`, as in RFC 9110. Without `last_modified` only
+/// `If-None-Match` is looked at
+pub fn is_not_modified(
+    headers: &HeaderMap,
+    etag: &str,
+    last_modified: Option<DateTime<Utc>>,
+) -> bool {
+    if let Some(if_none_match) = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+    {
+        return if_none_match
+            .split(',')
+            .map(|tag| tag.trim().trim_start_matches("W/"))
+            .any(|tag| tag == etag || tag == "*");
+    }
+
+    let since = headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| DateTime::parse_from_rfc2822(value).ok());
+    match (since, last_modified) {
+        (Some(since), Some(last_modified)) => last_modified.timestamp() <= since.timestamp(),
+        _ => false,
+    }
+}
+
+/// Date in the format of HTTP headers, like `Sun, 06 Nov 1994 08:49:37 GMT`
+fn http_date(time: DateTime<Utc>) -> String {
+    time.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// The `If-Match` header of a request that changes a resource, `None` when it was not sent
+///
+/// When `PreconditionConfig::require_if_match` is set requests without the header are rejected
+/// with 428, so nobody overwrites changes they have not seen
+#[derive(Debug, Clone)]
+pub struct IfMatch(pub Option<String>);
+
+impl IfMatch {
+    /// Whether the resource with this tag may be changed, weak tags never match
+    pub fn matches(&self, etag: &str) -> bool {
+        match &self.0 {
+            None => true,
+            Some(if_match) => if_match
+                .split(',')
+                .map(str::trim)
+                .any(|tag| tag == etag || tag == "*"),
+        }
+    }
+}
+
+impl<S> FromRequestParts<S> for IfMatch
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let if_match = match parts.headers.get(header::IF_MATCH) {
+            Some(value) => Some(
+                value
+                    .to_str()
+                    .map_err(|_| AppError::BadRequest("Invalid If-Match header".to_string()))?
+                    .to_string(),
+            ),
+            None => None,
+        };
+        let config = parts
+            .extensions
+            .get::<PreconditionConfig>()
+            .ok_or_else(|| {
+                AppError::Internal("PreconditionConfig extension is missing".to_string())
+            })?;
+        if if_match.is_none() && config.require_if_match {
+            return Err(AppError::PreconditionRequired(
+                "Send the ETag of the resource in If-Match to change it".to_string(),
+            ));
+        }
+
+        Ok(IfMatch(if_match))
+    }
+}
@@ -0,0 +1,495 @@
+use axum::{
+    http::{StatusCode, Uri},
+    response::IntoResponse,
+    Extension, Json,
+};
+use serde::Deserialize;
+use sqlx::{types::Json as SqlJson, PgPool};
+use utoipa::IntoParams;
+
+use crate::{
+    auth::{AdminUser, AuthUser},
+    blogs_api::{blog_headers, blog_not_found, fetch_blog},
+    errors::{AppError, ProblemDetails},
+    extractors::{AppPath, AppQuery, ValidatedJson},
+    models::{Blog, BlogMergePayload, DuplicatePair, RevisionAction, UserBlog},
+    pagination::PageParams,
+    revisions_api::{lock_blog_values, record_revision},
+};
+
+/// Title similarity from which two blogs are reported when no `threshold` is given
+const DEFAULT_THRESHOLD: f32 = 0.6;
+
+#[derive(Deserialize, IntoParams, Debug)]
+#[into_params(parameter_in = Query)]
+pub struct DuplicateParams {
+    /// Lowest trigram similarity of the titles of two blogs reported as duplicates
+    #[param(minimum = 0, maximum = 1, default = 0.6)]
+    pub threshold: Option<f32>,
+}
+
+/// Get likely duplicates
+///
+/// Returns a page of pairs of blogs that are likely the same article: their urls only differ in
+/// the query parameters or their titles are at least `threshold` similar. Pairs with the same
+/// page come first, then the most similar titles. The total number of pairs is returned in
+/// `X-Total-Count`
+#[utoipa::path(
+    get,
+    path = "/blogs/duplicates",
+    params(DuplicateParams, PageParams),
+    responses(
+        (status = 500, description = "Internal server error", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 400, description = "Invalid threshold or paging parameters", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 200, description = "Duplicates retrieved successfully", body=[DuplicatePair],
+            headers(
+                ("X-Total-Count" = i64, description = "Total number of pairs"),
+                ("Link" = String, description = "Links to the `prev` and `next` pages")
+            )
+        )
+    )
+)]
+pub async fn get_duplicates(
+    Extension(pool): Extension<PgPool>,
+    uri: Uri,
+    AppQuery(params): AppQuery<DuplicateParams>,
+    AppQuery(page_params): AppQuery<PageParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let page = page_params.offset_page()?;
+    let threshold = params.threshold.unwrap_or(DEFAULT_THRESHOLD);
+    if !(0.0..=1.0).contains(&threshold) {
+        return Err(AppError::invalid_field(
+            "Invalid query parameter",
+            "threshold",
+            "must be between 0 and 1".to_string(),
+        ));
+    }
+
+    let mut tx = pool.begin().await?;
+    // `%` compares titles to this threshold, and can use the trigram index on the titles
+    sqlx::query_scalar!(
+        "SELECT set_config('pg_trgm.similarity_threshold', $1, true)",
+        threshold.to_string()
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    // the pairs are found once for the page and the total, the page is empty past the last pair
+    let rows = sqlx::query!(
+        r#"WITH pairs AS MATERIALIZED (
+            SELECT a.id AS a_id, b.id AS b_id
+            FROM blogs a JOIN blogs b ON a.title % b.title AND a.id < b.id
+            WHERE a.deleted_at IS NULL AND b.deleted_at IS NULL
+            UNION
+            SELECT a.id, b.id
+            FROM blogs a JOIN blogs b
+                ON split_part(a.canonical_url, '?', 1) = split_part(b.canonical_url, '?', 1)
+                AND a.id < b.id
+            WHERE a.deleted_at IS NULL AND b.deleted_at IS NULL
+        )
+        SELECT counted.total AS "total!", page.blog AS "blog: SqlJson<UserBlog>",
+            page.duplicate AS "duplicate: SqlJson<UserBlog>", page.title_similarity,
+            page.same_page
+        FROM (SELECT COUNT(*) AS total FROM pairs) counted
+        LEFT JOIN LATERAL (
+            SELECT
+                jsonb_build_object('id', a.id, 'title', a.title, 'author', a.author, 'url', a.url, 'likes', a.likes)
+                    AS blog,
+                jsonb_build_object('id', b.id, 'title', b.title, 'author', b.author, 'url', b.url, 'likes', b.likes)
+                    AS duplicate,
+                similarity(a.title, b.title) AS title_similarity,
+                split_part(a.canonical_url, '?', 1) = split_part(b.canonical_url, '?', 1) AS same_page
+            FROM pairs
+            JOIN blogs a ON a.id = pairs.a_id
+            JOIN blogs b ON b.id = pairs.b_id
+            ORDER BY 4 DESC, 3 DESC, a.id, b.id
+            LIMIT $1 OFFSET $2
+        ) page ON true"#,
+        page.fetch_limit(),
+        page_params.offset.unwrap_or(0)
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    let total = rows.first().map_or(0, |row| row.total);
+    let pairs = rows
+        .into_iter()
+        .filter_map(|row| {
+            Some(DuplicatePair {
+                blog: row.blog?,
+                duplicate: row.duplicate?,
+                title_similarity: row.title_similarity?,
+                same_page: row.same_page?,
+            })
+        })
+        .collect();
+
+    let result = page.finish(pairs, |_| vec![]);
+    let headers = result.headers(&uri, page.limit, total);
+
+    Ok((StatusCode::OK, headers, Json(result.items)))
+}
+
+/// Merge a blog into another
+///
+/// Moves the likes, comments and tags of a blog to another one and deletes it for good, along
+/// with its revisions. The likes of both blogs are summed, counting users who liked both once.
+/// Returns the blog that was merged into.
+/// Only admins may merge blogs
+#[utoipa::path(
+    post,
+    path = "/blogs/{id}/merge",
+    params(("id" = i32, Path, description = "Id of the blog to merge")),
+    request_body = BlogMergePayload,
+    security(("bearer" = [])),
+    responses(
+        (status = 500, description = "Internal server error", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 404, description = "Blog not found", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 400, description = "Invalid blog id, malformed or invalid request body", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 401, description = "Missing, expired or invalid token", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 403, description = "User is not an admin", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 415, description = "Request body is not JSON", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 422, description = "Blog is merged into itself", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 200, description = "Blogs merged successfully", body=Blog,
+            headers(
+                ("ETag" = String, description = "Tag of this version of the blog"),
+                ("Last-Modified" = String, description = "When the blog was last edited")
+            )
+        )
+    )
+)]
+pub async fn merge_blog(
+    Extension(pool): Extension<PgPool>,
+    user: AuthUser,
+    _admin: AdminUser,
+    AppPath(id): AppPath<i32>,
+    ValidatedJson(body): ValidatedJson<BlogMergePayload>,
+) -> Result<impl IntoResponse, AppError> {
+    let into = body.into;
+    if id == into {
+        return Err(AppError::Unprocessable(format!(
+            "Blog with id={id} cannot be merged into itself"
+        )));
+    }
+
+    let mut tx = pool.begin().await?;
+    // rows are always locked in id order, so two merges of the same blogs cannot deadlock
+    let blogs = sqlx::query!(
+        "SELECT id, likes, comment_count FROM blogs
+        WHERE id = ANY($1) AND deleted_at IS NULL
+        ORDER BY id FOR UPDATE",
+        &[id, into][..]
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    let source = blogs
+        .iter()
+        .find(|blog| blog.id == id)
+        .ok_or_else(|| blog_not_found(id))?;
+    let old_values = lock_blog_values(&mut tx, into)
+        .await?
+        .ok_or_else(|| blog_not_found(into))?;
+
+    // moving rows does not fire the counting triggers, the counts are added up below
+    sqlx::query!(
+        "UPDATE blog_likes SET blog_id = $2
+        WHERE blog_id = $1 AND user_id NOT IN (SELECT user_id FROM blog_likes WHERE blog_id = $2)",
+        id,
+        into
+    )
+    .execute(&mut *tx)
+    .await?;
+    // the likes left behind are from users who liked both blogs, already counted for `into`
+    let shared_likes = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM blog_likes WHERE blog_id = $1"#,
+        id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "UPDATE comments SET blog_id = $2 WHERE blog_id = $1",
+        id,
+        into
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "INSERT INTO blog_tags (blog_id, tag_id)
+        SELECT $2, tag_id FROM blog_tags WHERE blog_id = $1
+        ON CONFLICT DO NOTHING",
+        id,
+        into
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "UPDATE blogs SET likes = likes + $2, comment_count = comment_count + $3 WHERE id = $1",
+        into,
+        source.likes - shared_likes as i32,
+        source.comment_count
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!("DELETE FROM blogs WHERE id = $1", id)
+        .execute(&mut *tx)
+        .await?;
+    record_revision(
+        &mut tx,
+        into,
+        user.id,
+        RevisionAction::Update,
+        Some(old_values),
+    )
+    .await?;
+    let blog = fetch_blog(&mut *tx, into)
+        .await?
+        .ok_or_else(|| blog_not_found(into))?;
+    tx.commit().await?;
+
+    Ok((StatusCode::OK, blog_headers(&blog), Json(blog)))
+}
+
+#[cfg(test)]
+mod duplicates_api_test {
+    use crate::test_helper::{insert_test_values, setup_test_context};
+    use axum::http::{header, StatusCode};
+    use rstest::*;
+    use serde_json::{json, Value};
+
+    // urls pointing to the same page cannot be submitted twice, unless the first one is trashed
+    #[rstest]
+    #[tokio::test]
+    async fn duplicate_urls() {
+        let mut ctx = setup_test_context().await;
+        ctx.login_as("andrea").await;
+        insert_test_values(&ctx.pool)
+            .await
+            .expect("Expected insert statement to work");
+
+        let response = ctx
+            .server
+            .post("/blogs")
+            .json(&json!({"title": "t", "author": "a", "url": "https://www.Example.com/post/?b=2&utm_source=x&a=1#top"}))
+            .await;
+        response.assert_status(StatusCode::CREATED);
+        assert_eq!(7, response.json::<Value>()["id"]);
+
+        for url in [
+            "http://example.com/post?a=1&b=2",
+            "https://example.com:443/post/?a=1&fbclid=x&b=2",
+        ] {
+            let response = ctx
+                .server
+                .post("/blogs")
+                .json(&json!({"title": "t", "author": "a", "url": url}))
+                .await;
+            response.assert_status(StatusCode::CONFLICT);
+            response.assert_header("location", "/blogs/7");
+            assert_eq!(
+                "The blog with id=7 has the same url",
+                response.json::<Value>()["detail"]
+            );
+        }
+        ctx.server
+            .post("/blogs")
+            .json(&json!({"title": "t", "author": "a", "url": "http://example.com/post?a=2&b=1"}))
+            .await
+            .assert_status(StatusCode::CREATED);
+
+        // changes to the url are checked too
+        let response = ctx
+            .server
+            .patch("/blogs/8")
+            .json(&json!({"url": "http://example.com/post?a=1&b=2"}))
+            .await;
+        response.assert_status(StatusCode::CONFLICT);
+        response.assert_header("location", "/blogs/7");
+        ctx.server
+            .patch("/blogs/7")
+            .json(&json!({"url": "http://example.com/post?a=1&b=2"}))
+            .await
+            .assert_status(StatusCode::OK);
+
+        // and so are the rows of an import
+        let response = ctx
+            .server
+            .post("/blogs/import")
+            .json(&json!([
+                {"title": "t", "author": "a", "url": "http://new.com"},
+                {"title": "t", "author": "a", "url": "https://example.com/post?b=2&a=1"},
+                {"title": "t", "author": "a", "url": "https://new.com/"}
+            ]))
+            .await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        assert_eq!(
+            json!({
+                "[1].url": ["already submitted as the blog with id=7"],
+                "[2].url": ["same as the url of row 0"]
+            }),
+            response.json::<Value>()["errors"]
+        );
+
+        // a trashed blog can be submitted again, but then not restored
+        ctx.server
+            .delete("/blogs/7")
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        let response = ctx
+            .server
+            .post("/blogs")
+            .json(&json!({"title": "t", "author": "a", "url": "https://example.com/post?a=1&b=2"}))
+            .await;
+        response.assert_status(StatusCode::CREATED);
+        let id = response.json::<Value>()["id"].clone();
+        let response = ctx.server.post("/blogs/7/restore").await;
+        response.assert_status(StatusCode::CONFLICT);
+        response.assert_header("location", format!("/blogs/{id}"));
+
+        // a port is only left out when it is the default one of the scheme
+        ctx.server
+            .post("/blogs")
+            .json(
+                &json!({"title": "t", "author": "a", "url": "http://example.com:443/post?a=1&b=2"}),
+            )
+            .await
+            .assert_status(StatusCode::CREATED);
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // blogs with the same page or similar titles are reported in pairs
+    #[rstest]
+    #[tokio::test]
+    async fn get_duplicates() {
+        let mut ctx = setup_test_context().await;
+        ctx.login_as("andrea").await;
+        insert_test_values(&ctx.pool)
+            .await
+            .expect("Expected insert statement to work");
+        for blog in [
+            json!({"title": "Understanding ownership in Rust", "author": "a", "url": "http://rust.com/own?id=1"}),
+            json!({"title": "Understanding ownership in Rust, part 2", "author": "b", "url": "http://other.com"}),
+            json!({"title": "Lifetimes", "author": "a", "url": "https://rust.com/own?id=2"}),
+        ] {
+            ctx.server
+                .post("/blogs")
+                .json(&blog)
+                .await
+                .assert_status(StatusCode::CREATED);
+        }
+
+        let response = ctx.server.get("/blogs/duplicates").await;
+        response.assert_status(StatusCode::OK);
+        response.assert_header("x-total-count", "2");
+        let pairs: Vec<Value> = response.json();
+        assert_eq!(
+            vec![(7, 9, true), (7, 8, false)],
+            pairs
+                .iter()
+                .map(|pair| (
+                    pair["blog"]["id"].as_i64().unwrap(),
+                    pair["duplicate"]["id"].as_i64().unwrap(),
+                    pair["same_page"].as_bool().unwrap()
+                ))
+                .collect::<Vec<_>>()
+        );
+        assert!(pairs[1]["title_similarity"].as_f64().unwrap() > 0.8);
+
+        let response = ctx.server.get("/blogs/duplicates?threshold=0.9").await;
+        response.assert_header("x-total-count", "1");
+        let response = ctx.server.get("/blogs/duplicates?offset=5").await;
+        response.assert_header("x-total-count", "2");
+        response.assert_json(&json!([]));
+        ctx.server
+            .get("/blogs/duplicates?threshold=2")
+            .await
+            .assert_status(StatusCode::BAD_REQUEST);
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // merging moves likes, comments and tags, only admins may merge
+    #[rstest]
+    #[tokio::test]
+    async fn merge_blog() {
+        let mut ctx = setup_test_context().await;
+        insert_test_values(&ctx.pool)
+            .await
+            .expect("Expected insert statement to work");
+        let (_, alice) = ctx.register("alice").await;
+        let (_, bob) = ctx.register("bob").await;
+        for (user, endpoint) in [
+            (&alice, "/blogs/2/like"),
+            (&alice, "/blogs/1/like"),
+            (&bob, "/blogs/2/like"),
+        ] {
+            ctx.server
+                .post(endpoint)
+                .add_header(header::AUTHORIZATION, user)
+                .await
+                .assert_status(StatusCode::OK);
+        }
+
+        ctx.login_as("andrea").await;
+        ctx.server
+            .post("/blogs/2/merge")
+            .json(&json!({"into": 1}))
+            .await
+            .assert_status(StatusCode::FORBIDDEN);
+
+        ctx.login_as_admin("root").await;
+        ctx.server
+            .patch("/blogs/2")
+            .json(&json!({"tags": ["testing"]}))
+            .await
+            .assert_status(StatusCode::OK);
+        ctx.server
+            .post("/blogs/2/comments")
+            .json(&json!({"body": "first"}))
+            .await
+            .assert_status(StatusCode::CREATED);
+        let response = ctx
+            .server
+            .post("/blogs/2/merge")
+            .json(&json!({"into": 1}))
+            .await;
+        response.assert_status(StatusCode::OK);
+        let blog: Value = response.json();
+        // 7 + 1 and 5 + 2, alice liked both and counts once
+        assert_eq!(14, blog["likes"]);
+        assert_eq!(1, blog["comment_count"]);
+        assert_eq!(json!(["testing"]), blog["tags"]);
+        ctx.server
+            .get("/blogs/2")
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+        let comments: Vec<Value> = ctx.server.get("/blogs/1/comments").await.json();
+        assert_eq!("first", comments[0]["body"]);
+
+        ctx.server
+            .post("/blogs/1/merge")
+            .json(&json!({"into": 1}))
+            .await
+            .assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+        ctx.server
+            .post("/blogs/1/merge")
+            .json(&json!({"into": 2}))
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+
+        // the moved likes can be taken back
+        ctx.server.clear_headers();
+        for (user, likes) in [(&alice, 13), (&bob, 12)] {
+            ctx.server
+                .delete("/blogs/1/like")
+                .add_header(header::AUTHORIZATION, user)
+                .await
+                .assert_json(&json!({"blog_id": 1, "likes": likes, "liked": false}));
+        }
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+}
@@ -1,7 +1,210 @@
+use std::collections::BTreeMap;
+
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
 use serde::{Deserialize, Serialize};
+use sqlx::error::ErrorKind;
+use tracing::error;
 use utoipa::ToSchema;
+use validator::ValidationErrors;
+
+pub const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+/// Field name -> list of problems with that field
+pub type FieldErrors = BTreeMap<String, Vec<String>>;
+
+/// RFC 7807 problem details, returned as `application/problem+json` for every error
+#[derive(ToSchema, Deserialize, Serialize, Clone, Debug)]
+pub struct ProblemDetails {
+    /// URI identifying the problem type, `about:blank` when the status code says it all
+    #[serde(rename = "type")]
+    #[schema(example = "about:blank")]
+    pub problem_type: String,
+    /// Short summary of the problem type
+    #[schema(example = "Not Found")]
+    pub title: String,
+    /// HTTP status code
+    #[schema(example = 404)]
+    pub status: u16,
+    /// Explanation specific to this occurrence of the problem
+    #[schema(example = "Blog with id=1 not found")]
+    pub detail: String,
+    /// Path of the request that caused the problem
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "/blogs/1")]
+    pub instance: Option<String>,
+    /// Problems with single fields of the request
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub errors: FieldErrors,
+}
+
+/// Errors returned by the handlers and extractors
+///
+/// Every variant is turned into the matching status code and a `ProblemDetails` body,
+/// internal errors are logged and their details are not sent to the client
+#[derive(Debug)]
+pub enum AppError {
+    NotFound(String),
+    BadRequest(String),
+    Unauthorized(String),
+    Forbidden(String),
+    InvalidFields(String, FieldErrors),
+    UnsupportedMediaType(String),
+    Conflict(String),
+    /// Conflict with an existing resource, its path is sent in `Location`
+    Duplicate(String, String),
+    Unprocessable(String),
+    PreconditionFailed(String),
+    PreconditionRequired(String),
+    Internal(String),
+}
+
+impl AppError {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::BadRequest(_) | AppError::InvalidFields(_, _) => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            AppError::Conflict(_) | AppError::Duplicate(_, _) => StatusCode::CONFLICT,
+            AppError::Unprocessable(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+            AppError::PreconditionRequired(_) => StatusCode::PRECONDITION_REQUIRED,
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Single field error
+    pub fn invalid_field(detail: &str, field: &str, message: String) -> Self {
+        AppError::InvalidFields(
+            detail.to_string(),
+            FieldErrors::from([(field.to_string(), vec![message])]),
+        )
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(e: sqlx::Error) -> Self {
+        match &e {
+            sqlx::Error::RowNotFound => AppError::NotFound("Resource not found".to_string()),
+            sqlx::Error::Database(db_err) => match db_err.kind() {
+                ErrorKind::UniqueViolation => {
+                    AppError::Conflict("Resource already exists".to_string())
+                }
+                ErrorKind::ForeignKeyViolation => {
+                    AppError::Conflict("Resource is referenced by another resource".to_string())
+                }
+                ErrorKind::CheckViolation | ErrorKind::NotNullViolation => {
+                    AppError::Unprocessable(format!("Invalid resource: {}", db_err.message()))
+                }
+                _ => AppError::Internal(e.to_string()),
+            },
+            _ => AppError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<ValidationErrors> for AppError {
+    fn from(e: ValidationErrors) -> Self {
+        let errors = e
+            .field_errors()
+            .into_iter()
+            .map(|(field, errors)| {
+                let messages = errors
+                    .iter()
+                    .map(|error| match &error.message {
+                        Some(message) => message.to_string(),
+                        None => format!("failed `{}` validation", error.code),
+                    })
+                    .collect();
+                (field.to_string(), messages)
+            })
+            .collect();
+
+        AppError::InvalidFields("Request validation failed".to_string(), errors)
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status_code();
+        // tells the client how to authenticate
+        let challenge = matches!(self, AppError::Unauthorized(_))
+            .then(|| (header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer")));
+        // points the client to the resource it conflicts with
+        let location = match &self {
+            AppError::Duplicate(_, location) => HeaderValue::from_str(location).ok(),
+            _ => None,
+        };
+        let (detail, errors) = match self {
+            AppError::Internal(e) => {
+                error!("Internal server error: {}", e);
+                ("Internal server error".to_string(), FieldErrors::new())
+            }
+            AppError::InvalidFields(detail, errors) => (detail, errors),
+            AppError::NotFound(m)
+            | AppError::BadRequest(m)
+            | AppError::Unauthorized(m)
+            | AppError::Forbidden(m)
+            | AppError::UnsupportedMediaType(m)
+            | AppError::Conflict(m)
+            | AppError::Duplicate(m, _)
+            | AppError::Unprocessable(m)
+            | AppError::PreconditionFailed(m)
+            | AppError::PreconditionRequired(m) => (m, FieldErrors::new()),
+        };
+
+        let problem = ProblemDetails {
+            problem_type: "about:blank".to_string(),
+            title: status.canonical_reason().unwrap_or_default().to_string(),
+            status: status.as_u16(),
+            detail,
+            instance: None,
+            errors,
+        };
+
+        let mut response = (
+            status,
+            [(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static(PROBLEM_CONTENT_TYPE),
+            )],
+            Json(problem.clone()),
+        )
+            .into_response();
+        if let Some((name, value)) = challenge {
+            response.headers_mut().insert(name, value);
+        }
+        if let Some(location) = location {
+            response.headers_mut().insert(header::LOCATION, location);
+        }
+        // picked up by `problem_instance` to fill in the request path
+        response.extensions_mut().insert(problem);
+        response
+    }
+}
+
+/// Middleware setting the `instance` of problem responses to the request path
+pub async fn problem_instance(req: Request, next: Next) -> Response {
+    let instance = req.uri().path().to_string();
+    let mut response = next.run(req).await;
+
+    let Some(mut problem) = response.extensions_mut().remove::<ProblemDetails>() else {
+        return response;
+    };
+    problem.instance = Some(instance);
 
-#[derive(ToSchema, Deserialize, Serialize)]
-pub struct ClientError {
-    pub message: String,
+    let (mut parts, _) = response.into_parts();
+    parts.headers.remove(header::CONTENT_LENGTH);
+    match serde_json::to_vec(&problem) {
+        Ok(body) => Response::from_parts(parts, Body::from(body)),
+        Err(e) => AppError::Internal(e.to_string()).into_response(),
+    }
 }
@@ -0,0 +1,327 @@
+use axum::{
+    body::Bytes,
+    extract::{
+        path::ErrorKind as PathErrorKind, rejection::PathRejection, FromRequest, FromRequestParts,
+        Path, RawPathParams, Request,
+    },
+    http::{header, request::Parts, HeaderMap},
+};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer};
+use serde_json::Value;
+use validator::Validate;
+
+use crate::errors::AppError;
+
+/// Drop-in replacement for `axum::Json` whose rejections are `AppError`s
+///
+/// Accepts `application/json` and any `application/*+json` content type
+pub struct AppJson<T>(pub T);
+
+impl<T, S> FromRequest<S> for AppJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        if !is_json_content_type(req.headers()) {
+            return Err(AppError::UnsupportedMediaType(
+                "Expected request with `Content-Type: application/json`".to_string(),
+            ));
+        }
+
+        let bytes = Bytes::from_request(req, state)
+            .await
+            .map_err(|e| AppError::BadRequest(e.body_text()))?;
+
+        deserialize_json(&bytes).map(AppJson)
+    }
+}
+
+/// Like `AppJson`, and the body must also pass its `Validate` rules
+pub struct ValidatedJson<T>(pub T);
+
+impl<T, S> FromRequest<S> for ValidatedJson<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let AppJson(value) = AppJson::<T>::from_request(req, state).await?;
+        value.validate()?;
+        Ok(ValidatedJson(value))
+    }
+}
+
+pub const MERGE_PATCH_CONTENT_TYPE: &str = "application/merge-patch+json";
+pub const JSON_PATCH_CONTENT_TYPE: &str = "application/json-patch+json";
+
+/// Body of a PATCH request, the format is picked from the content type
+pub enum PatchBody {
+    /// RFC 7396 merge patch, sent as `application/merge-patch+json` or `application/json`
+    Merge(Value),
+    /// RFC 6902 list of operations, sent as `application/json-patch+json`
+    JsonPatch(json_patch::Patch),
+}
+
+impl<S> FromRequest<S> for PatchBody
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let essence = content_type(req.headers()).map(|mime| mime.essence_str().to_string());
+        let bytes = Bytes::from_request(req, state)
+            .await
+            .map_err(|e| AppError::BadRequest(e.body_text()))?;
+
+        match essence.as_deref() {
+            Some(MERGE_PATCH_CONTENT_TYPE | "application/json") => {
+                deserialize_json(&bytes).map(PatchBody::Merge)
+            }
+            Some(JSON_PATCH_CONTENT_TYPE) => deserialize_json(&bytes).map(PatchBody::JsonPatch),
+            _ => Err(AppError::UnsupportedMediaType(format!(
+                "Expected request with `Content-Type: {MERGE_PATCH_CONTENT_TYPE}` or `Content-Type: {JSON_PATCH_CONTENT_TYPE}`"
+            ))),
+        }
+    }
+}
+
+pub const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+pub const CSV_CONTENT_TYPE: &str = "text/csv";
+
+/// Rows of an upload, each one a JSON object or the reason it could not be read
+///
+/// Accepts a JSON array, newline delimited JSON or CSV with a header row. CSV rows become objects
+/// keyed by the header, the `tags` column holds tags separated by spaces, commas or semicolons
+pub struct ImportBody(pub Vec<Result<Value, String>>);
+
+impl<S> FromRequest<S> for ImportBody
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let essence = content_type(req.headers()).map(|mime| mime.essence_str().to_string());
+        let bytes = Bytes::from_request(req, state)
+            .await
+            .map_err(|e| AppError::BadRequest(e.body_text()))?;
+
+        let rows = match essence.as_deref() {
+            Some("application/json") => {
+                let rows: Vec<Value> = serde_json::from_slice(&bytes).map_err(|e| {
+                    AppError::BadRequest(format!("Expected a JSON array of blogs: {e}"))
+                })?;
+                rows.into_iter().map(Ok).collect()
+            }
+            Some(NDJSON_CONTENT_TYPE) => String::from_utf8_lossy(&bytes)
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| {
+                    serde_json::from_str(line).map_err(|e| format!("Malformed JSON: {e}"))
+                })
+                .collect(),
+            Some(CSV_CONTENT_TYPE) => csv_rows(&bytes)?,
+            _ => {
+                return Err(AppError::UnsupportedMediaType(format!(
+                    "Expected request with `Content-Type: application/json`, `Content-Type: {NDJSON_CONTENT_TYPE}` or `Content-Type: {CSV_CONTENT_TYPE}`"
+                )))
+            }
+        };
+
+        Ok(ImportBody(rows))
+    }
+}
+
+fn csv_rows(bytes: &[u8]) -> Result<Vec<Result<Value, String>>, AppError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(bytes);
+    let headers = reader
+        .headers()
+        .map_err(|e| AppError::BadRequest(format!("Malformed CSV header: {e}")))?
+        .clone();
+
+    Ok(reader
+        .records()
+        .map(|record| {
+            let record = record.map_err(|e| format!("Malformed CSV: {e}"))?;
+            let row = headers
+                .iter()
+                .zip(record.iter())
+                .map(|(column, field)| {
+                    let value = match column {
+                        "tags" => field
+                            .split(|c: char| c.is_whitespace() || c == ',' || c == ';')
+                            .filter(|tag| !tag.is_empty())
+                            .map(Value::from)
+                            .collect(),
+                        _ => Value::from(field),
+                    };
+                    (column.to_string(), value)
+                })
+                .collect();
+            Ok(Value::Object(row))
+        })
+        .collect())
+}
+
+/// Drop-in replacement for `axum::extract::Query` whose rejections are `AppError`s
+///
+/// Repeated keys like `?tag=a&tag=b` can be collected into a `Vec`
+pub struct AppQuery<T>(pub T);
+
+impl<T, S> FromRequestParts<S> for AppQuery<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let query = parts.uri.query().unwrap_or_default();
+        let deserializer = serde_html_form::Deserializer::from_bytes(query.as_bytes());
+        serde_path_to_error::deserialize(deserializer)
+            .map(AppQuery)
+            .map_err(|e| {
+                let path = e.path().to_string();
+                let message = e.into_inner().to_string();
+                let field = field_name(path, &message);
+                AppError::invalid_field("Invalid query parameter", &field, message)
+            })
+    }
+}
+
+/// Drop-in replacement for `axum::extract::Path` whose rejections are `AppError`s
+pub struct AppPath<T>(pub T);
+
+impl<T, S> FromRequestParts<S> for AppPath<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match Path::<T>::from_request_parts(parts, state).await {
+            Ok(Path(value)) => Ok(AppPath(value)),
+            Err(PathRejection::FailedToDeserializePathParams(e)) => {
+                let detail = "Invalid path parameter";
+                // a single value like `Path<i32>` carries no key, use the route's parameter name
+                let single_key = match RawPathParams::from_request_parts(parts, state).await {
+                    Ok(params) if params.iter().count() == 1 => params
+                        .iter()
+                        .next()
+                        .map(|(key, _)| key.to_string())
+                        .unwrap_or_default(),
+                    _ => "path".to_string(),
+                };
+                Err(match e.kind() {
+                    PathErrorKind::ParseErrorAtKey {
+                        key,
+                        value,
+                        expected_type,
+                    } => AppError::invalid_field(
+                        detail,
+                        key,
+                        format!("`{value}` is not a valid {expected_type}"),
+                    ),
+                    PathErrorKind::ParseError {
+                        value,
+                        expected_type,
+                    }
+                    | PathErrorKind::ParseErrorAtIndex {
+                        value,
+                        expected_type,
+                        ..
+                    } => AppError::invalid_field(
+                        detail,
+                        &single_key,
+                        format!("`{value}` is not a valid {expected_type}"),
+                    ),
+                    PathErrorKind::InvalidUtf8InPathParam { key } => {
+                        AppError::invalid_field(detail, key, "is not valid UTF-8".to_string())
+                    }
+                    _ => AppError::BadRequest(e.body_text()),
+                })
+            }
+            // the route has no parameters, a bug of the server rather than of the request
+            Err(PathRejection::MissingPathParams(e)) => Err(AppError::Internal(e.body_text())),
+            Err(e) => Err(AppError::BadRequest(e.body_text())),
+        }
+    }
+}
+
+/// Deserializes a JSON body, reporting the path of the offending field on data errors
+pub fn deserialize_json<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, AppError> {
+    deserialize_reporting_path(&mut serde_json::Deserializer::from_slice(bytes))
+}
+
+/// Like `deserialize_json`, for an already parsed JSON document
+pub fn deserialize_json_value<T: DeserializeOwned>(value: Value) -> Result<T, AppError> {
+    deserialize_reporting_path(value)
+}
+
+fn deserialize_reporting_path<'de, D, T>(deserializer: D) -> Result<T, AppError>
+where
+    D: Deserializer<'de, Error = serde_json::Error>,
+    T: Deserialize<'de>,
+{
+    serde_path_to_error::deserialize(deserializer).map_err(|e| {
+        let path = e.path().to_string();
+        let inner = e.into_inner();
+        match inner.classify() {
+            serde_json::error::Category::Data => {
+                let message = inner.to_string();
+                let field = field_name(path, &message);
+                let message = message
+                    .split(" at line ")
+                    .next()
+                    .unwrap_or_default()
+                    .to_string();
+                AppError::invalid_field("Request body has invalid fields", &field, message)
+            }
+            _ => AppError::BadRequest(format!("Malformed JSON body: {inner}")),
+        }
+    })
+}
+
+/// Name of the field a deserialization error is about
+fn field_name(path: String, message: &str) -> String {
+    // serde reports missing and unknown fields on the parent object
+    match message
+        .strip_prefix("missing field `")
+        .or_else(|| message.strip_prefix("unknown field `"))
+    {
+        Some(rest) => {
+            let name = rest.split('`').next().unwrap_or_default();
+            if path == "." {
+                name.to_string()
+            } else {
+                format!("{path}.{name}")
+            }
+        }
+        None => path,
+    }
+}
+
+fn content_type(headers: &HeaderMap) -> Option<mime::Mime> {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<mime::Mime>().ok())
+}
+
+fn is_json_content_type(headers: &HeaderMap) -> bool {
+    let Some(content_type) = content_type(headers) else {
+        return false;
+    };
+
+    content_type.type_() == "application"
+        && (content_type.subtype() == "json" || content_type.suffix().is_some_and(|s| s == "json"))
+}
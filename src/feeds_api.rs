@@ -0,0 +1,503 @@
+use axum::{
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Extension,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::json;
+use sqlx::PgPool;
+
+use crate::{
+    blog_filters::{BlogFilter, BlogSortParams},
+    blogs_api::list_blogs,
+    conditional::{hash_etag, is_not_modified, validator_headers},
+    config::FeedConfig,
+    errors::{AppError, ProblemDetails},
+    extractors::AppQuery,
+    models::Blog,
+    pagination::{Page, PageMode},
+};
+
+/// Number of blogs in a feed, the most recently added ones
+pub const FEED_SIZE: i64 = 50;
+
+const FEED_TITLE: &str = "Bloglist";
+const FEED_DESCRIPTION: &str = "Blogs shared by the team";
+
+/// Get the RSS feed
+///
+/// Returns the most recently added blogs as RSS 2.0, newest first, narrowed down by the same
+/// filters as `GET /blogs`. Supports conditional requests with `If-None-Match`
+#[utoipa::path(
+    get,
+    path = "/feed.rss",
+    params(BlogFilter),
+    responses(
+        (status = 500, description = "Internal server error", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 400, description = "Invalid filter parameters", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 304, description = "Feed did not change"),
+        (status = 200, description = "Feed retrieved successfully", body=String, content_type="application/rss+xml",
+            headers(
+                ("ETag" = String, description = "Tag of this version of the feed"),
+                ("Last-Modified" = String, description = "When the newest blog of the feed last changed")
+            )
+        )
+    )
+)]
+pub async fn rss_feed(
+    Extension(pool): Extension<PgPool>,
+    Extension(feed_config): Extension<FeedConfig>,
+    headers: HeaderMap,
+    AppQuery(filter): AppQuery<BlogFilter>,
+) -> Result<Response, AppError> {
+    let blogs = feed_blogs(&pool, &filter).await?;
+    let base = xml_escape(&feed_config.base_url);
+
+    let items: String = blogs
+        .iter()
+        .map(|blog| {
+            let categories: String = blog
+                .tags
+                .iter()
+                .map(|tag| format!("<category>{}</category>", xml_escape(tag)))
+                .collect();
+            format!(
+                "<item><title>{}</title><link>{}</link><guid isPermaLink=\"false\">{}</guid><dc:creator>{}</dc:creator><pubDate>{}</pubDate>{categories}</item>",
+                xml_escape(&blog.title),
+                xml_escape(&blog.url),
+                blog_urn(blog),
+                xml_escape(&blog.author),
+                blog.created_at.to_rfc2822(),
+            )
+        })
+        .collect();
+    let body = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\"><channel><title>{FEED_TITLE}</title><link>{base}/blogs</link><description>{FEED_DESCRIPTION}</description><atom:link href=\"{base}/feed.rss\" rel=\"self\" type=\"application/rss+xml\"/>{items}</channel></rss>\n"
+    );
+
+    Ok(feed_response(
+        &headers,
+        "application/rss+xml; charset=utf-8",
+        body,
+        last_modified(&blogs),
+    ))
+}
+
+/// Get the Atom feed
+///
+/// Returns the most recently added blogs as an Atom feed, newest first, narrowed down by the
+/// same filters as `GET /blogs`. Supports conditional requests with `If-None-Match`
+#[utoipa::path(
+    get,
+    path = "/feed.atom",
+    params(BlogFilter),
+    responses(
+        (status = 500, description = "Internal server error", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 400, description = "Invalid filter parameters", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 304, description = "Feed did not change"),
+        (status = 200, description = "Feed retrieved successfully", body=String, content_type="application/atom+xml",
+            headers(
+                ("ETag" = String, description = "Tag of this version of the feed"),
+                ("Last-Modified" = String, description = "When the newest blog of the feed last changed")
+            )
+        )
+    )
+)]
+pub async fn atom_feed(
+    Extension(pool): Extension<PgPool>,
+    Extension(feed_config): Extension<FeedConfig>,
+    headers: HeaderMap,
+    AppQuery(filter): AppQuery<BlogFilter>,
+) -> Result<Response, AppError> {
+    let blogs = feed_blogs(&pool, &filter).await?;
+    let base = xml_escape(&feed_config.base_url);
+    let updated = last_modified(&blogs).unwrap_or(DateTime::UNIX_EPOCH);
+
+    let entries: String = blogs
+        .iter()
+        .map(|blog| {
+            let categories: String = blog
+                .tags
+                .iter()
+                .map(|tag| format!("<category term=\"{}\"/>", xml_escape(tag)))
+                .collect();
+            format!(
+                "<entry><id>{}</id><title>{}</title><link href=\"{}\"/><author><name>{}</name></author><published>{}</published><updated>{}</updated>{categories}</entry>",
+                blog_urn(blog),
+                xml_escape(&blog.title),
+                xml_escape(&blog.url),
+                xml_escape(&blog.author),
+                rfc3339(blog.created_at),
+                rfc3339(blog.updated_at),
+            )
+        })
+        .collect();
+    let body = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<feed xmlns=\"http://www.w3.org/2005/Atom\"><id>{base}/feed.atom</id><title>{FEED_TITLE}</title><subtitle>{FEED_DESCRIPTION}</subtitle><updated>{}</updated><link href=\"{base}/feed.atom\" rel=\"self\"/><link href=\"{base}/blogs\"/>{entries}</feed>\n",
+        rfc3339(updated),
+    );
+
+    Ok(feed_response(
+        &headers,
+        "application/atom+xml; charset=utf-8",
+        body,
+        last_modified(&blogs),
+    ))
+}
+
+/// Get the JSON feed
+///
+/// Returns the most recently added blogs as a JSON Feed 1.1, newest first, narrowed down by
+/// the same filters as `GET /blogs`. Supports conditional requests with `If-None-Match`
+#[utoipa::path(
+    get,
+    path = "/feed.json",
+    params(BlogFilter),
+    responses(
+        (status = 500, description = "Internal server error", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 400, description = "Invalid filter parameters", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 304, description = "Feed did not change"),
+        (status = 200, description = "Feed retrieved successfully", body=Object, content_type="application/feed+json",
+            headers(
+                ("ETag" = String, description = "Tag of this version of the feed"),
+                ("Last-Modified" = String, description = "When the newest blog of the feed last changed")
+            )
+        )
+    )
+)]
+pub async fn json_feed(
+    Extension(pool): Extension<PgPool>,
+    Extension(feed_config): Extension<FeedConfig>,
+    headers: HeaderMap,
+    AppQuery(filter): AppQuery<BlogFilter>,
+) -> Result<Response, AppError> {
+    let blogs = feed_blogs(&pool, &filter).await?;
+    let base = &feed_config.base_url;
+
+    let items: Vec<_> = blogs
+        .iter()
+        .map(|blog| {
+            json!({
+                "id": blog_urn(blog),
+                "url": blog.url,
+                "title": blog.title,
+                "content_text": format!("{} by {}", blog.title, blog.author),
+                "authors": [{"name": blog.author}],
+                "tags": blog.tags,
+                "date_published": rfc3339(blog.created_at),
+                "date_modified": rfc3339(blog.updated_at),
+            })
+        })
+        .collect();
+    let body = json!({
+        "version": "https://jsonfeed.org/version/1.1",
+        "title": FEED_TITLE,
+        "description": FEED_DESCRIPTION,
+        "home_page_url": format!("{base}/blogs"),
+        "feed_url": format!("{base}/feed.json"),
+        "items": items,
+    });
+
+    Ok(feed_response(
+        &headers,
+        "application/feed+json",
+        body.to_string(),
+        last_modified(&blogs),
+    ))
+}
+
+/// Get the OPML outline
+///
+/// Returns the same blogs as the feeds as an OPML 2.0 outline, to import them in feed readers
+/// and outliners
+#[utoipa::path(
+    get,
+    path = "/feed.opml",
+    params(BlogFilter),
+    responses(
+        (status = 500, description = "Internal server error", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 400, description = "Invalid filter parameters", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 304, description = "Outline did not change"),
+        (status = 200, description = "Outline retrieved successfully", body=String, content_type="text/x-opml",
+            headers(
+                ("ETag" = String, description = "Tag of this version of the outline"),
+                ("Last-Modified" = String, description = "When the newest blog of the outline last changed")
+            )
+        )
+    )
+)]
+pub async fn opml_feed(
+    Extension(pool): Extension<PgPool>,
+    headers: HeaderMap,
+    AppQuery(filter): AppQuery<BlogFilter>,
+) -> Result<Response, AppError> {
+    let blogs = feed_blogs(&pool, &filter).await?;
+    let updated = last_modified(&blogs).unwrap_or(DateTime::UNIX_EPOCH);
+
+    let outlines: String = blogs
+        .iter()
+        .map(|blog| {
+            format!(
+                "<outline type=\"link\" text=\"{}\" title=\"{}\" url=\"{}\" created=\"{}\"/>",
+                xml_escape(&blog.title),
+                xml_escape(&format!("{} by {}", blog.title, blog.author)),
+                xml_escape(&blog.url),
+                blog.created_at.to_rfc2822(),
+            )
+        })
+        .collect();
+    let body = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<opml version=\"2.0\"><head><title>{FEED_TITLE}</title><dateModified>{}</dateModified></head><body>{outlines}</body></opml>\n",
+        updated.to_rfc2822(),
+    );
+
+    Ok(feed_response(
+        &headers,
+        "text/x-opml; charset=utf-8",
+        body,
+        last_modified(&blogs),
+    ))
+}
+
+/// The newest blogs matching the filter, through the same query as `GET /blogs`
+async fn feed_blogs(pool: &PgPool, filter: &BlogFilter) -> Result<Vec<Blog>, AppError> {
+    let sort = BlogSortParams {
+        sort: Some("-created_at".to_string()),
+    }
+    .sort()?;
+    let page = Page {
+        limit: FEED_SIZE,
+        mode: PageMode::Start,
+    };
+
+    let mut blogs = list_blogs(pool, filter, &sort, &page).await?;
+    blogs.truncate(FEED_SIZE as usize);
+    Ok(blogs)
+}
+
+/// The feed, or 304 when the client already has this version of it
+fn feed_response(
+    request_headers: &HeaderMap,
+    content_type: &'static str,
+    body: String,
+    last_modified: Option<DateTime<Utc>>,
+) -> Response {
+    // If-Modified-Since is not honoured: trashing a blog or retagging one changes the feed
+    // without making any of its dates newer, only the tag of the body tells the versions apart
+    let etag = hash_etag(&body);
+    let headers = validator_headers(&etag, last_modified);
+
+    if is_not_modified(request_headers, &etag, None) {
+        return (StatusCode::NOT_MODIFIED, headers).into_response();
+    }
+    (
+        StatusCode::OK,
+        headers,
+        [(header::CONTENT_TYPE, HeaderValue::from_static(content_type))],
+        body,
+    )
+        .into_response()
+}
+
+/// When the newest blog of the feed last changed, the date of the feed itself
+fn last_modified(blogs: &[Blog]) -> Option<DateTime<Utc>> {
+    blogs.iter().map(|blog| blog.updated_at).max()
+}
+
+/// Stable id of a blog in the feeds, it does not change when the blog is edited
+fn blog_urn(blog: &Blog) -> String {
+    format!("urn:bloglist:blog:{}", blog.id)
+}
+
+fn rfc3339(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod feeds_api_test {
+    use crate::test_helper::{
+        insert_test_values, setup_test_context, setup_test_context_with, test_settings,
+    };
+    use axum::http::{header, StatusCode};
+    use rstest::*;
+    use serde_json::{json, Value};
+
+    // every feed lists the filtered blogs, newest first, with its own content type
+    #[rstest]
+    #[case::rss("/feed.rss", "application/rss+xml; charset=utf-8", "<item>")]
+    #[case::atom("/feed.atom", "application/atom+xml; charset=utf-8", "<entry>")]
+    #[case::json("/feed.json", "application/feed+json", "\"urn:bloglist:blog:")]
+    #[case::opml("/feed.opml", "text/x-opml; charset=utf-8", "<outline ")]
+    #[tokio::test]
+    async fn feeds(#[case] path: &str, #[case] content_type: &str, #[case] item: &str) {
+        let ctx = setup_test_context().await;
+        insert_test_values(&ctx.pool)
+            .await
+            .expect("Expected insert statement to work");
+
+        let response = ctx.server.get(path).await;
+        response.assert_status(StatusCode::OK);
+        response.assert_header(header::CONTENT_TYPE, content_type);
+        response.assert_header(header::LAST_MODIFIED, "Mon, 06 Jan 2025 10:00:00 GMT");
+        let body = response.text();
+        assert_eq!(6, body.matches(item).count());
+        let newest = body.find("First class tests").unwrap();
+        let oldest = body.find("React patterns").unwrap();
+        assert!(newest < oldest);
+
+        let response = ctx
+            .server
+            .get(&format!("{path}?author=Robert%20C.%20Martin"))
+            .await;
+        assert_eq!(3, response.text().matches(item).count());
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // titles are escaped and tags become categories
+    #[rstest]
+    #[tokio::test]
+    async fn feed_content() {
+        let mut ctx = setup_test_context().await;
+        ctx.login_as("root").await;
+        ctx.server
+            .post("/blogs")
+            .json(&json!({"title": "Rust & <Tests>", "author": "a", "url": "http://a.com/?x=1&y=2", "tags": ["rust"]}))
+            .await
+            .assert_status(StatusCode::CREATED);
+        ctx.server
+            .post("/blogs")
+            .json(&json!({"title": "Untagged", "author": "a", "url": "http://b.com"}))
+            .await
+            .assert_status(StatusCode::CREATED);
+
+        let rss = ctx.server.get("/feed.rss?tag=rust").await.text();
+        assert!(rss.contains("<title>Rust &amp; &lt;Tests&gt;</title>"));
+        assert!(rss.contains("<link>http://a.com/?x=1&amp;y=2</link>"));
+        assert!(rss.contains("<category>rust</category>"));
+        assert!(!rss.contains("Untagged"));
+
+        let feed: Value = ctx.server.get("/feed.json?tag=rust").await.json();
+        assert_eq!("https://jsonfeed.org/version/1.1", feed["version"]);
+        assert_eq!(
+            json!([{
+                "id": "urn:bloglist:blog:1",
+                "url": "http://a.com/?x=1&y=2",
+                "title": "Rust & <Tests>",
+                "content_text": "Rust & <Tests> by a",
+                "authors": [{"name": "a"}],
+                "tags": ["rust"],
+                "date_published": feed["items"][0]["date_published"],
+                "date_modified": feed["items"][0]["date_modified"],
+            }]),
+            feed["items"]
+        );
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // readers polling an unchanged feed get 304 without a body
+    #[rstest]
+    #[tokio::test]
+    async fn conditional_feed_requests() {
+        let mut ctx = setup_test_context().await;
+        insert_test_values(&ctx.pool)
+            .await
+            .expect("Expected insert statement to work");
+
+        let response = ctx.server.get("/feed.atom").await;
+        let etag = response.header(header::ETAG);
+
+        let response = ctx
+            .server
+            .get("/feed.atom")
+            .add_header(header::IF_NONE_MATCH, etag.clone())
+            .await;
+        response.assert_status(StatusCode::NOT_MODIFIED);
+        assert!(response.as_bytes().is_empty());
+        ctx.server
+            .get("/feed.atom")
+            .add_header(header::IF_NONE_MATCH, "\"other\"")
+            .await
+            .assert_status(StatusCode::OK);
+
+        // trashing a blog leaves the newest update as it was, the feed still changes
+        ctx.login_as_admin("root").await;
+        ctx.server
+            .delete("/blogs/1")
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        let response = ctx
+            .server
+            .get("/feed.atom")
+            .add_header(header::IF_NONE_MATCH, etag.clone())
+            .await;
+        response.assert_status(StatusCode::OK);
+        let trashed_etag = response.header(header::ETAG);
+        assert_ne!(etag, trashed_etag);
+        let etag = trashed_etag;
+        // so the dates alone do not make a 304
+        ctx.server
+            .get("/feed.atom")
+            .add_header(
+                header::IF_MODIFIED_SINCE,
+                response.header(header::LAST_MODIFIED),
+            )
+            .await
+            .assert_status(StatusCode::OK);
+
+        ctx.server
+            .post("/blogs")
+            .json(&json!({"title": "t", "author": "a", "url": "http://a.com"}))
+            .await
+            .assert_status(StatusCode::CREATED);
+        let response = ctx
+            .server
+            .get("/feed.atom")
+            .add_header(header::IF_NONE_MATCH, etag.clone())
+            .await;
+        response.assert_status(StatusCode::OK);
+        assert_ne!(etag, response.header(header::ETAG));
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // links point to the configured public url, whatever host the request names
+    #[rstest]
+    #[case::rss("/feed.rss")]
+    #[case::atom("/feed.atom")]
+    #[case::json("/feed.json")]
+    #[tokio::test]
+    async fn feed_links(#[case] path: &str) {
+        let mut settings = test_settings();
+        settings.server.public_url = Some("https://blogs.example.com/".to_string());
+        let ctx = setup_test_context_with(settings).await;
+
+        let body = ctx
+            .server
+            .get(path)
+            .add_header(header::HOST, "evil.com")
+            .add_header("x-forwarded-proto", "ftp")
+            .await
+            .text();
+        assert!(body.contains(&format!("https://blogs.example.com{path}")));
+        assert!(body.contains("https://blogs.example.com/blogs"));
+        assert!(!body.contains("evil.com"));
+        assert!(!body.contains("ftp"));
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+}
@@ -0,0 +1,302 @@
+use std::{collections::HashMap, future::Future, time::Duration as StdDuration};
+
+use axum::{http::StatusCode, response::IntoResponse, Extension, Json};
+use sqlx::{migrate::Migrator, PgPool};
+
+use crate::{
+    models::{HealthCheck, Liveness, MigrationsCheck, PoolStats, Readiness, ReadinessChecks},
+    shutdown::Shutdown,
+};
+
+/// The migrations the server was built with, the database must have all of them
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// How long a check may wait for the database, so probes answer before the orchestrator gives up
+const CHECK_TIMEOUT: StdDuration = StdDuration::from_secs(2);
+
+/// Liveness probe
+///
+/// Answers as long as the process runs, without touching the database. A failing database makes
+/// the server unready, restarting it would not help
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    responses(
+        (status = 200, description = "The process is alive", body=Liveness)
+    )
+)]
+pub async fn healthz() -> impl IntoResponse {
+    (StatusCode::OK, Json(Liveness { alive: true }))
+}
+
+/// Readiness probe
+///
+/// Tells whether the server should get traffic: it is not shutting down, the database answers a
+/// trivial query and has every migration the server was built with. Answers 503 when any check
+/// fails, with the outcome of each of them and the connections of the pool
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    responses(
+        (status = 503, description = "The server should not get traffic", body=Readiness),
+        (status = 200, description = "The server is ready", body=Readiness)
+    )
+)]
+pub async fn readyz(
+    Extension(pool): Extension<PgPool>,
+    Extension(shutdown): Extension<Shutdown>,
+) -> impl IntoResponse {
+    // taken first, the checks below use a connection themselves
+    let stats = PoolStats {
+        size: pool.size(),
+        idle: pool.num_idle(),
+        max_connections: pool.options().get_max_connections(),
+    };
+    let shutdown = if shutdown.is_triggered() {
+        failed("the server is shutting down")
+    } else {
+        passed()
+    };
+    let (database, migrations) = tokio::join!(check_database(&pool), check_migrations(&pool));
+
+    let ready = shutdown.ok && database.ok && migrations.ok;
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (
+        status,
+        Json(Readiness {
+            ready,
+            checks: ReadinessChecks {
+                shutdown,
+                database,
+                migrations,
+                pool: stats,
+            },
+        }),
+    )
+}
+
+async fn check_database(pool: &PgPool) -> HealthCheck {
+    match within_timeout(sqlx::query_scalar!("SELECT 1").fetch_one(pool)).await {
+        Ok(_) => passed(),
+        Err(detail) => failed(&detail),
+    }
+}
+
+/// Compares the migrations the server was built with to the ones recorded in the database
+async fn check_migrations(pool: &PgPool) -> MigrationsCheck {
+    let shipped: Vec<_> = MIGRATOR
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .collect();
+    let mut check = MigrationsCheck {
+        ok: false,
+        detail: None,
+        expected: shipped.len(),
+        applied: 0,
+        pending: vec![],
+        changed: vec![],
+    };
+
+    // the table is created by the migrator, not by the migrations checked at compile time
+    let rows = within_timeout(
+        sqlx::query_as::<_, (i64, bool, Vec<u8>)>(
+            "SELECT version, success, checksum FROM _sqlx_migrations",
+        )
+        .fetch_all(pool),
+    )
+    .await;
+    let applied: HashMap<i64, (bool, Vec<u8>)> = match rows {
+        Ok(rows) => rows
+            .into_iter()
+            .map(|(version, success, checksum)| (version, (success, checksum)))
+            .collect(),
+        Err(detail) => {
+            check.detail = Some(detail);
+            return check;
+        }
+    };
+
+    for migration in shipped {
+        match applied.get(&migration.version) {
+            Some((true, checksum)) if *checksum == *migration.checksum => check.applied += 1,
+            Some((true, _)) => {
+                check.applied += 1;
+                check.changed.push(migration.version);
+            }
+            _ => check.pending.push(migration.version),
+        }
+    }
+    let mut problems = vec![];
+    if !check.pending.is_empty() {
+        problems.push(format!(
+            "{} migrations are not applied",
+            check.pending.len()
+        ));
+    }
+    if !check.changed.is_empty() {
+        problems.push(format!(
+            "{} applied migrations have changed since",
+            check.changed.len()
+        ));
+    }
+    check.ok = problems.is_empty();
+    if !check.ok {
+        check.detail = Some(problems.join(", "));
+    }
+
+    check
+}
+
+/// Runs a query for at most `CHECK_TIMEOUT`, turning its failure into the detail of a check
+async fn within_timeout<T>(
+    query: impl Future<Output = Result<T, sqlx::Error>>,
+) -> Result<T, String> {
+    match tokio::time::timeout(CHECK_TIMEOUT, query).await {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(e)) => Err(format!("the database failed: {e}")),
+        Err(_) => Err(format!(
+            "the database did not answer within {} seconds",
+            CHECK_TIMEOUT.as_secs()
+        )),
+    }
+}
+
+fn passed() -> HealthCheck {
+    HealthCheck {
+        ok: true,
+        detail: None,
+    }
+}
+
+fn failed(detail: &str) -> HealthCheck {
+    HealthCheck {
+        ok: false,
+        detail: Some(detail.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod health_api_test {
+    use crate::test_helper::setup_test_context;
+    use axum::http::StatusCode;
+    use rstest::*;
+    use serde_json::{json, Value};
+
+    // a server on a migrated database is ready, and alive
+    #[rstest]
+    #[tokio::test]
+    async fn ready() {
+        let ctx = setup_test_context().await;
+
+        let response = ctx.server.get("/healthz").await;
+        response.assert_status(StatusCode::OK);
+        response.assert_json(&json!({"alive": true}));
+
+        let response = ctx.server.get("/readyz").await;
+        response.assert_status(StatusCode::OK);
+        let body = response.json::<Value>();
+        assert_eq!(json!(true), body["ready"]);
+        assert_eq!(json!({"ok": true}), body["checks"]["shutdown"]);
+        assert_eq!(json!({"ok": true}), body["checks"]["database"]);
+        let migrations = &body["checks"]["migrations"];
+        assert_eq!(json!(true), migrations["ok"]);
+        assert!(migrations["expected"].as_u64().unwrap() > 0);
+        assert_eq!(migrations["expected"], migrations["applied"]);
+        assert_eq!(json!([]), migrations["pending"]);
+        assert_eq!(json!([]), migrations["changed"]);
+        assert!(body["checks"]["pool"]["size"].as_u64().unwrap() >= 1);
+        assert_eq!(json!(10), body["checks"]["pool"]["max_connections"]);
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // readiness fails as soon as the shutdown starts
+    #[rstest]
+    #[tokio::test]
+    async fn readyz_during_shutdown() {
+        let ctx = setup_test_context().await;
+
+        ctx.shutdown.trigger();
+        let response = ctx.server.get("/readyz").await;
+        response.assert_status(StatusCode::SERVICE_UNAVAILABLE);
+        let body = response.json::<Value>();
+        assert_eq!(json!(false), body["ready"]);
+        assert_eq!(
+            json!({"ok": false, "detail": "the server is shutting down"}),
+            body["checks"]["shutdown"]
+        );
+        assert_eq!(json!({"ok": true}), body["checks"]["database"]);
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // migrations missing from or changed in the database make the server unready
+    #[rstest]
+    #[tokio::test]
+    async fn readyz_migrations() {
+        let ctx = setup_test_context().await;
+        let versions: Vec<i64> =
+            sqlx::query_scalar("SELECT version FROM _sqlx_migrations ORDER BY version")
+                .fetch_all(&ctx.pool)
+                .await
+                .unwrap();
+        let (first, last) = (versions[0], versions[versions.len() - 1]);
+        sqlx::query("DELETE FROM _sqlx_migrations WHERE version = $1")
+            .bind(last)
+            .execute(&ctx.pool)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE _sqlx_migrations SET checksum = '\\x00' WHERE version = $1")
+            .bind(first)
+            .execute(&ctx.pool)
+            .await
+            .unwrap();
+
+        let response = ctx.server.get("/readyz").await;
+        response.assert_status(StatusCode::SERVICE_UNAVAILABLE);
+        let body = response.json::<Value>();
+        assert_eq!(json!(false), body["ready"]);
+        let migrations = &body["checks"]["migrations"];
+        assert_eq!(json!(false), migrations["ok"]);
+        assert_eq!(
+            json!("1 migrations are not applied, 1 applied migrations have changed since"),
+            migrations["detail"]
+        );
+        assert_eq!(json!(versions.len() - 1), migrations["applied"]);
+        assert_eq!(json!([last]), migrations["pending"]);
+        assert_eq!(json!([first]), migrations["changed"]);
+        assert_eq!(json!({"ok": true}), body["checks"]["database"]);
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // without a database the server is unready, but still alive
+    #[rstest]
+    #[tokio::test]
+    async fn readyz_without_database() {
+        let ctx = setup_test_context().await;
+        ctx.pool.close().await;
+
+        let response = ctx.server.get("/readyz").await;
+        response.assert_status(StatusCode::SERVICE_UNAVAILABLE);
+        let body = response.json::<Value>();
+        assert_eq!(json!(false), body["ready"]);
+        assert_eq!(json!(false), body["checks"]["database"]["ok"]);
+        assert_eq!(json!(false), body["checks"]["migrations"]["ok"]);
+        assert_eq!(json!(0), body["checks"]["migrations"]["applied"]);
+        assert_eq!(json!({"ok": true}), body["checks"]["shutdown"]);
+
+        let response = ctx.server.get("/healthz").await;
+        response.assert_status(StatusCode::OK);
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+}
@@ -0,0 +1,449 @@
+use std::time::Duration as StdDuration;
+
+use axum::{
+    body::{to_bytes, Body},
+    extract::{FromRequestParts, Request},
+    http::{header, HeaderName, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use sha2::{Digest, Sha256};
+use sqlx::{types::Json as SqlJson, PgPool};
+use tracing::{error, info};
+
+use crate::{auth::AuthUser, config::IdempotencyConfig, errors::AppError, shutdown::Shutdown};
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+/// Set on responses that were stored for an earlier request with the same key
+pub const REPLAYED_HEADER: &str = "idempotent-replayed";
+
+const MAX_KEY_LENGTH: usize = 255;
+/// Bodies of requests and responses are buffered to be hashed and stored, as large as axum
+/// accepts by default
+const MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+/// A request still running after this long is taken to have died, and its key is free again
+const ABANDONED_AFTER_SECONDS: f64 = 60.0;
+/// How often expired keys are removed
+const PURGE_INTERVAL: StdDuration = StdDuration::from_secs(60 * 60);
+
+/// Middleware making a write safe to retry with an `Idempotency-Key` header
+///
+/// The first request with a key runs as usual and, when it succeeds, its response is stored
+/// for `IdempotencyConfig::ttl`. Retries with the same key get the stored response back, with
+/// `Idempotent-Replayed: true`, instead of running again. A key reused for a different request
+/// is rejected with 422, one whose first request is still running with 409. Failed requests are
+/// not stored, so they can be fixed and retried with the same key. Requests without the header
+/// are not affected
+///
+/// Keys belong to the logged in user, so users cannot collide and a refreshed token still gets
+/// the stored response back. Requests with a key must be logged in
+pub async fn idempotent(request: Request, next: Next) -> Result<Response, AppError> {
+    let Some(key) = request.headers().get(IDEMPOTENCY_KEY_HEADER) else {
+        return Ok(next.run(request).await);
+    };
+    let key = key
+        .to_str()
+        .ok()
+        .filter(|key| !key.is_empty() && key.len() <= MAX_KEY_LENGTH)
+        .ok_or_else(|| {
+            AppError::BadRequest(format!(
+                "Idempotency-Key must be 1 to {MAX_KEY_LENGTH} visible ASCII characters"
+            ))
+        })?
+        .to_string();
+    let pool = request
+        .extensions()
+        .get::<PgPool>()
+        .cloned()
+        .ok_or_else(|| AppError::Internal("PgPool extension is missing".to_string()))?;
+    let config = request
+        .extensions()
+        .get::<IdempotencyConfig>()
+        .cloned()
+        .ok_or_else(|| AppError::Internal("IdempotencyConfig extension is missing".to_string()))?;
+
+    let (mut parts, body) = request.into_parts();
+    let user = AuthUser::from_request_parts(&mut parts, &()).await?;
+    let body = to_bytes(body, MAX_BODY_BYTES)
+        .await
+        .map_err(|e| AppError::BadRequest(format!("Failed to read the request body: {e}")))?;
+    let fingerprint = fingerprint(&parts, user.id, &body);
+
+    // take the key, unless another request holds it
+    let taken = sqlx::query_scalar!(
+        "INSERT INTO idempotency_keys (user_id, key, fingerprint, expires_at)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (user_id, key) DO UPDATE SET
+            fingerprint = EXCLUDED.fingerprint,
+            status = NULL,
+            headers = NULL,
+            body = NULL,
+            created_at = now(),
+            expires_at = EXCLUDED.expires_at
+        WHERE idempotency_keys.expires_at < now()
+            OR (idempotency_keys.status IS NULL
+                AND idempotency_keys.created_at < now() - make_interval(secs => $5))
+        RETURNING key",
+        user.id,
+        key,
+        fingerprint,
+        Utc::now() + config.ttl,
+        ABANDONED_AFTER_SECONDS
+    )
+    .fetch_optional(&pool)
+    .await?;
+
+    if taken.is_none() {
+        return replay(&pool, user.id, &key, &fingerprint).await;
+    }
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+    if !response.status().is_success() {
+        release(&pool, user.id, &key).await;
+        return Ok(response);
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = match to_bytes(body, MAX_BODY_BYTES).await {
+        Ok(body) => body,
+        Err(e) => {
+            release(&pool, user.id, &key).await;
+            return Err(AppError::Internal(format!(
+                "Failed to read the response body: {e}"
+            )));
+        }
+    };
+    let headers: Vec<(String, String)> = parts
+        .headers
+        .iter()
+        .filter(|(name, _)| *name != header::CONTENT_LENGTH)
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect();
+    sqlx::query!(
+        "UPDATE idempotency_keys SET status = $3, headers = $4, body = $5
+        WHERE user_id = $1 AND key = $2",
+        user.id,
+        key,
+        parts.status.as_u16() as i16,
+        SqlJson(headers) as _,
+        &body[..]
+    )
+    .execute(&pool)
+    .await?;
+
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
+
+/// The stored response of the request that took the key
+async fn replay(
+    pool: &PgPool,
+    user_id: i32,
+    key: &str,
+    fingerprint: &[u8],
+) -> Result<Response, AppError> {
+    let stored = sqlx::query!(
+        r#"SELECT fingerprint, status, headers AS "headers: SqlJson<Vec<(String, String)>>", body
+        FROM idempotency_keys WHERE user_id = $1 AND key = $2"#,
+        user_id,
+        key
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| {
+        AppError::Conflict(format!(
+            "The request with Idempotency-Key `{key}` just finished, retry it"
+        ))
+    })?;
+    if stored.fingerprint != fingerprint {
+        return Err(AppError::Unprocessable(format!(
+            "Idempotency-Key `{key}` was already used for a different request"
+        )));
+    }
+    let (Some(status), Some(SqlJson(headers)), Some(body)) =
+        (stored.status, stored.headers, stored.body)
+    else {
+        return Err(AppError::Conflict(format!(
+            "The request with Idempotency-Key `{key}` is still running"
+        )));
+    };
+
+    let mut response = Body::from(body).into_response();
+    *response.status_mut() = u16::try_from(status)
+        .ok()
+        .and_then(|status| status.try_into().ok())
+        .ok_or_else(|| AppError::Internal(format!("Invalid stored status {status}")))?;
+    for (name, value) in headers {
+        if let (Ok(name), Ok(value)) = (HeaderName::try_from(name), HeaderValue::try_from(value)) {
+            response.headers_mut().append(name, value);
+        }
+    }
+    response
+        .headers_mut()
+        .insert(REPLAYED_HEADER, HeaderValue::from_static("true"));
+
+    Ok(response)
+}
+
+/// Frees a key whose request failed, so it can be retried
+async fn release(pool: &PgPool, user_id: i32, key: &str) {
+    if let Err(e) = sqlx::query!(
+        "DELETE FROM idempotency_keys WHERE user_id = $1 AND key = $2 AND status IS NULL",
+        user_id,
+        key
+    )
+    .execute(pool)
+    .await
+    {
+        error!("Failed to release Idempotency-Key `{}`: {}", key, e);
+    }
+}
+
+/// Hash of what makes two requests the same: method, path, user and body
+///
+/// The user rather than their token, which changes whenever it is refreshed
+fn fingerprint(parts: &axum::http::request::Parts, user_id: i32, body: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    for part in [
+        parts.method.as_str().as_bytes(),
+        parts.uri.path().as_bytes(),
+        &user_id.to_be_bytes(),
+        body,
+    ] {
+        hasher.update((part.len() as u64).to_be_bytes());
+        hasher.update(part);
+    }
+    hasher.finalize().to_vec()
+}
+
+/// Removes the expired keys every `PURGE_INTERVAL` until `shutdown` is triggered, a removal
+/// already running is finished first
+pub async fn purge_periodically(pool: PgPool, shutdown: Shutdown) {
+    let mut interval = tokio::time::interval(PURGE_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.triggered() => return,
+        }
+        match sqlx::query!("DELETE FROM idempotency_keys WHERE expires_at < now()")
+            .execute(&pool)
+            .await
+        {
+            Ok(result) if result.rows_affected() > 0 => {
+                info!(
+                    "Removed {} expired idempotency keys",
+                    result.rows_affected()
+                )
+            }
+            Ok(_) => {}
+            Err(e) => error!("Failed to remove expired idempotency keys: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod idempotency_test {
+    use crate::{
+        auth::issue_token,
+        test_helper::{setup_test_context, test_auth_config},
+    };
+    use axum::http::{header, StatusCode};
+    use chrono::Duration;
+    use rstest::*;
+    use serde_json::{json, Value};
+
+    // a retried create returns the first response and creates nothing
+    #[rstest]
+    #[tokio::test]
+    async fn retried_create_blog() {
+        let mut ctx = setup_test_context().await;
+        ctx.login_as("alice").await;
+        let blog = json!({"title": "t", "author": "a", "url": "http://a.com"});
+
+        let first = ctx
+            .server
+            .post("/blogs")
+            .add_header("idempotency-key", "k1")
+            .json(&blog)
+            .await;
+        first.assert_status(StatusCode::CREATED);
+        assert!(first.maybe_header("idempotent-replayed").is_none());
+        let retry = ctx
+            .server
+            .post("/blogs")
+            .add_header("idempotency-key", "k1")
+            .json(&blog)
+            .await;
+        retry.assert_status(StatusCode::CREATED);
+        retry.assert_header("idempotent-replayed", "true");
+        retry.assert_header("etag", first.header("etag"));
+        assert_eq!(first.json::<Value>(), retry.json::<Value>());
+        ctx.server
+            .get("/blogs")
+            .await
+            .assert_header("x-total-count", "1");
+
+        // the same key for another request is an error
+        let response = ctx
+            .server
+            .post("/blogs")
+            .add_header("idempotency-key", "k1")
+            .json(&json!({"title": "other", "author": "a", "url": "http://a.com"}))
+            .await;
+        response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+
+        // other keys and no key create blogs as usual
+        ctx.server
+            .post("/blogs")
+            .add_header("idempotency-key", "k2")
+            .json(&json!({"title": "t", "author": "a", "url": "http://b.com"}))
+            .await
+            .assert_status(StatusCode::CREATED);
+        ctx.server
+            .post("/blogs")
+            .json(&json!({"title": "t", "author": "a", "url": "http://c.com"}))
+            .await;
+        ctx.server
+            .get("/blogs")
+            .await
+            .assert_header("x-total-count", "3");
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // failed requests can be retried with the same key, invalid keys are rejected
+    #[rstest]
+    #[tokio::test]
+    async fn failed_requests_and_invalid_keys() {
+        let mut ctx = setup_test_context().await;
+        ctx.login_as("alice").await;
+        let blog = json!({"title": "t", "author": "a", "url": "http://a.com"});
+
+        ctx.server
+            .post("/blogs/1/comments")
+            .add_header("idempotency-key", "k1")
+            .json(&json!({"body": "Hi"}))
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+        ctx.server.post("/blogs").json(&blog).await;
+        let response = ctx
+            .server
+            .post("/blogs/1/comments")
+            .add_header("idempotency-key", "k1")
+            .json(&json!({"body": "Hi"}))
+            .await;
+        response.assert_status(StatusCode::CREATED);
+        assert!(response.maybe_header("idempotent-replayed").is_none());
+
+        for key in ["", &"k".repeat(256)] {
+            ctx.server
+                .post("/blogs")
+                .add_header("idempotency-key", key)
+                .json(&blog)
+                .await
+                .assert_status(StatusCode::BAD_REQUEST);
+        }
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // keys are free again once expired, and held while their request runs
+    #[rstest]
+    #[tokio::test]
+    async fn expired_and_running_keys() {
+        let mut ctx = setup_test_context().await;
+        ctx.login_as("carol").await;
+        sqlx::query("INSERT INTO blogs (title, author, url) VALUES ('t', 'a', 'http://a.com')")
+            .execute(&ctx.pool)
+            .await
+            .unwrap();
+        let like = || {
+            ctx.server
+                .post("/blogs/1/like")
+                .add_header("idempotency-key", "k1")
+        };
+
+        like()
+            .await
+            .assert_json(&json!({"blog_id": 1, "likes": 1, "liked": true}));
+        ctx.server.delete("/blogs/1/like").await;
+        like().await.assert_header("idempotent-replayed", "true");
+
+        sqlx::query("UPDATE idempotency_keys SET expires_at = now() - INTERVAL '1 second'")
+            .execute(&ctx.pool)
+            .await
+            .unwrap();
+        let response = like().await;
+        assert!(response.maybe_header("idempotent-replayed").is_none());
+        response.assert_json(&json!({"blog_id": 1, "likes": 1, "liked": true}));
+
+        sqlx::query("UPDATE idempotency_keys SET status = NULL, headers = NULL, body = NULL")
+            .execute(&ctx.pool)
+            .await
+            .unwrap();
+        like().await.assert_status(StatusCode::CONFLICT);
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // keys belong to a user: other users can use them too, a new token of the same user replays
+    #[rstest]
+    #[tokio::test]
+    async fn keys_per_user() {
+        let mut ctx = setup_test_context().await;
+        let alice = ctx.login_as("alice").await;
+        let first = ctx
+            .server
+            .post("/blogs")
+            .add_header("idempotency-key", "k1")
+            .json(&json!({"title": "t", "author": "a", "url": "http://a.com"}))
+            .await;
+        first.assert_status(StatusCode::CREATED);
+
+        ctx.login_as("bob").await;
+        let response = ctx
+            .server
+            .post("/blogs")
+            .add_header("idempotency-key", "k1")
+            .json(&json!({"title": "t", "author": "a", "url": "http://b.com"}))
+            .await;
+        response.assert_status(StatusCode::CREATED);
+        assert!(response.maybe_header("idempotent-replayed").is_none());
+
+        // a token expiring later than the first one, as after a refresh
+        let mut config = test_auth_config();
+        config.token_lifetime += Duration::hours(1);
+        let (token, _) = issue_token(&config, alice, "alice").unwrap();
+        ctx.server.clear_headers();
+        ctx.server
+            .add_header(header::AUTHORIZATION, format!("Bearer {token}"));
+        let retry = ctx
+            .server
+            .post("/blogs")
+            .add_header("idempotency-key", "k1")
+            .json(&json!({"title": "t", "author": "a", "url": "http://a.com"}))
+            .await;
+        retry.assert_status(StatusCode::CREATED);
+        retry.assert_header("idempotent-replayed", "true");
+        assert_eq!(first.json::<Value>(), retry.json::<Value>());
+        ctx.server
+            .get("/blogs")
+            .await
+            .assert_header("x-total-count", "2");
+
+        // without a user the key cannot be looked up
+        ctx.server.clear_headers();
+        ctx.server
+            .post("/blogs")
+            .add_header("idempotency-key", "k1")
+            .json(&json!({"title": "t", "author": "a", "url": "http://a.com"}))
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+}
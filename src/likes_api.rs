@@ -0,0 +1,239 @@
+use axum::{http::StatusCode, response::IntoResponse, Extension, Json};
+use sqlx::{error::ErrorKind, PgPool};
+
+use crate::{
+    auth::AuthUser,
+    blogs_api::blog_not_found,
+    errors::{AppError, ProblemDetails},
+    extractors::AppPath,
+    models::BlogLikes,
+};
+
+/// Like a blog
+///
+/// Adds the like of the logged in user to a blog, any user may like any blog. Liking a blog twice
+/// counts once. Returns the new number of likes
+#[utoipa::path(
+    post,
+    path = "/blogs/{id}/like",
+    params(
+        ("id" = i32, Path, description = "Blog id"),
+        ("Idempotency-Key" = Option<String>, Header, description = "Makes retries return the first response instead of running again")
+    ),
+    security(("bearer" = [])),
+    responses(
+        (status = 500, description = "Internal server error", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 404, description = "Blog not found", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 401, description = "Missing, expired or invalid token", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 400, description = "Invalid blog id", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 200, description = "Blog liked", body=BlogLikes)
+    )
+)]
+pub async fn like_blog(
+    Extension(pool): Extension<PgPool>,
+    user: AuthUser,
+    AppPath(id): AppPath<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let mut tx = pool.begin().await?;
+
+    // the trigger on blog_likes updates blogs.likes in the same transaction
+    sqlx::query!(
+        "INSERT INTO blog_likes (blog_id, user_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        id,
+        user.id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| match &e {
+        sqlx::Error::Database(db_err) if db_err.kind() == ErrorKind::ForeignKeyViolation => {
+            blog_not_found(id)
+        }
+        _ => AppError::from(e),
+    })?;
+    let likes = sqlx::query_scalar!(
+        "SELECT likes FROM blogs WHERE id = $1 AND deleted_at IS NULL",
+        id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| blog_not_found(id))?;
+    tx.commit().await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(BlogLikes {
+            blog_id: id,
+            likes,
+            liked: true,
+        }),
+    ))
+}
+
+/// Unlike a blog
+///
+/// Removes the like of the logged in user from a blog. Nothing happens if the user did not like
+/// it. Returns the new number of likes
+#[utoipa::path(
+    delete,
+    path = "/blogs/{id}/like",
+    params(
+        ("id" = i32, Path, description = "Blog id")
+    ),
+    security(("bearer" = [])),
+    responses(
+        (status = 500, description = "Internal server error", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 404, description = "Blog not found", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 401, description = "Missing, expired or invalid token", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 400, description = "Invalid blog id", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 200, description = "Blog unliked", body=BlogLikes)
+    )
+)]
+pub async fn unlike_blog(
+    Extension(pool): Extension<PgPool>,
+    user: AuthUser,
+    AppPath(id): AppPath<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!(
+        "DELETE FROM blog_likes WHERE blog_id = $1 AND user_id = $2",
+        id,
+        user.id
+    )
+    .execute(&mut *tx)
+    .await?;
+    let likes = sqlx::query_scalar!(
+        "SELECT likes FROM blogs WHERE id = $1 AND deleted_at IS NULL",
+        id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| blog_not_found(id))?;
+    tx.commit().await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(BlogLikes {
+            blog_id: id,
+            likes,
+            liked: false,
+        }),
+    ))
+}
+
+#[cfg(test)]
+mod likes_api_test {
+    use crate::test_helper::{insert_test_values, setup_test_context};
+    use axum::http::{header, StatusCode};
+    use rstest::*;
+    use serde_json::{json, Value};
+
+    // each user's like counts once and can be taken back
+    #[rstest]
+    #[tokio::test]
+    async fn like_unlike_blog() {
+        let ctx = setup_test_context().await;
+        insert_test_values(&ctx.pool)
+            .await
+            .expect("Expected insert statement to work");
+        let (_, alice) = ctx.register("alice").await;
+        let (_, bob) = ctx.register("bob").await;
+        let (_, carol) = ctx.register("carol").await;
+
+        for (user, expected_likes) in [(&alice, 8), (&alice, 8), (&bob, 9)] {
+            let response = ctx
+                .server
+                .post("/blogs/1/like")
+                .add_header(header::AUTHORIZATION, user)
+                .await;
+            response.assert_status(StatusCode::OK);
+            response.assert_json(&json!({"blog_id": 1, "likes": expected_likes, "liked": true}));
+        }
+
+        for (user, expected_likes) in [(&alice, 8), (&alice, 8), (&carol, 8)] {
+            let response = ctx
+                .server
+                .delete("/blogs/1/like")
+                .add_header(header::AUTHORIZATION, user)
+                .await;
+            response.assert_status(StatusCode::OK);
+            response.assert_json(&json!({"blog_id": 1, "likes": expected_likes, "liked": false}));
+        }
+
+        let blog: Value = ctx.server.get("/blogs/1").await.json();
+        assert_eq!(8, blog["likes"]);
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // concurrent likes are all counted
+    #[rstest]
+    #[tokio::test]
+    async fn concurrent_likes() {
+        let ctx = setup_test_context().await;
+        insert_test_values(&ctx.pool)
+            .await
+            .expect("Expected insert statement to work");
+        let mut users = vec![];
+        for username in ["alice", "bob", "carol", "dave"] {
+            users.push(ctx.register(username).await.1);
+        }
+
+        let like = |user: &String| {
+            ctx.server
+                .post("/blogs/5/like")
+                .add_header(header::AUTHORIZATION, user)
+        };
+        let responses = tokio::join!(
+            like(&users[0]),
+            like(&users[1]),
+            like(&users[2]),
+            like(&users[3]),
+            like(&users[0])
+        );
+        for response in [
+            responses.0,
+            responses.1,
+            responses.2,
+            responses.3,
+            responses.4,
+        ] {
+            response.assert_status(StatusCode::OK);
+        }
+
+        let blog: Value = ctx.server.get("/blogs/5").await.json();
+        assert_eq!(4, blog["likes"]);
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // the blog must exist and the caller must be logged in
+    #[rstest]
+    #[case::missing_blog("/blogs/42/like", true, StatusCode::NOT_FOUND)]
+    #[case::not_logged_in("/blogs/1/like", false, StatusCode::UNAUTHORIZED)]
+    #[tokio::test]
+    async fn like_blog_errors(
+        #[case] endpoint: &str,
+        #[case] logged_in: bool,
+        #[case] expected_status_code: StatusCode,
+    ) {
+        let mut ctx = setup_test_context().await;
+        insert_test_values(&ctx.pool)
+            .await
+            .expect("Expected insert statement to work");
+        if logged_in {
+            ctx.login_as("alice").await;
+        }
+
+        for request in [ctx.server.post(endpoint), ctx.server.delete(endpoint)] {
+            request.await.assert_status(expected_status_code);
+        }
+        let blog: Value = ctx.server.get("/blogs/1").await.json();
+        assert_eq!(7, blog["likes"]);
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+}
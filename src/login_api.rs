@@ -0,0 +1,140 @@
+use axum::{http::StatusCode, response::IntoResponse, Extension, Json};
+use sqlx::PgPool;
+
+use crate::{
+    auth::issue_token,
+    config::AuthConfig,
+    errors::{AppError, ProblemDetails},
+    extractors::AppJson,
+    models::{LoginPayload, LoginResponse},
+    passwords::verify_password,
+};
+
+/// Hash of no one's password, checked when the user does not exist so that the answer takes as
+/// long as for a wrong password and does not tell which usernames exist
+const DUMMY_PASSWORD_HASH: &str =
+    "$argon2id$v=19$m=19456,t=2,p=1$A+pj30IVebQG+/FBTpdbcA$ftYJ8RB3mIzRHaQDtGL1wykK6WrnR9UJ2kqCISxNQsI";
+
+/// Log in
+///
+/// Checks the credentials of a user and returns a signed token, send it as
+/// `Authorization: Bearer <token>` to the routes that need a user
+#[utoipa::path(
+    post,
+    path = "/login",
+    request_body = LoginPayload,
+    responses(
+        (status = 500, description = "Internal server error", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 400, description = "Malformed request body", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 415, description = "Request body is not JSON", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 401, description = "Invalid username or password", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 200, description = "Logged in", body=LoginResponse)
+    )
+)]
+pub async fn login(
+    Extension(pool): Extension<PgPool>,
+    Extension(config): Extension<AuthConfig>,
+    AppJson(body): AppJson<LoginPayload>,
+) -> Result<impl IntoResponse, AppError> {
+    let invalid_credentials = || AppError::Unauthorized("Invalid username or password".to_string());
+
+    let user = sqlx::query!(
+        "SELECT id, username, name, password_hash FROM users WHERE username = $1",
+        body.username
+    )
+    .fetch_optional(&pool)
+    .await?;
+    let Some(user) = user else {
+        verify_password(body.password, DUMMY_PASSWORD_HASH.to_string()).await?;
+        return Err(invalid_credentials());
+    };
+    if !verify_password(body.password, user.password_hash).await? {
+        return Err(invalid_credentials());
+    }
+
+    let (token, expires_at) = issue_token(&config, user.id, &user.username)?;
+
+    Ok((
+        StatusCode::OK,
+        Json(LoginResponse {
+            token,
+            expires_at,
+            username: user.username,
+            name: user.name,
+        }),
+    ))
+}
+
+#[cfg(test)]
+mod login_api_test {
+    use crate::test_helper::{setup_test_context, TEST_PASSWORD};
+    use axum::http::{header, StatusCode};
+    use rstest::*;
+    use serde_json::{json, Value};
+
+    // the returned token lets the user create blogs
+    #[rstest]
+    #[tokio::test]
+    async fn login_returns_usable_token() {
+        let ctx = setup_test_context().await;
+        ctx.server
+            .post("/users")
+            .json(&json!({"username": "root", "name": "Superuser", "password": TEST_PASSWORD}))
+            .await
+            .assert_status(StatusCode::CREATED);
+
+        let response = ctx
+            .server
+            .post("/login")
+            .json(&json!({"username": "root", "password": TEST_PASSWORD}))
+            .await;
+
+        response.assert_status(StatusCode::OK);
+        let body: Value = response.json();
+        assert_eq!("root", body["username"]);
+        assert_eq!("Superuser", body["name"]);
+        let token = body["token"].as_str().unwrap();
+
+        let response = ctx
+            .server
+            .post("/blogs")
+            .authorization_bearer(token)
+            .json(&json!({"title": "t", "author": "a", "url": "http://a.com"}))
+            .await;
+        response.assert_status(StatusCode::CREATED);
+        assert_eq!("root", response.json::<Value>()["user"]["username"]);
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // wrong credentials get the same answer whether the user exists or not
+    #[rstest]
+    #[case::wrong_password("root", "wrong password")]
+    #[case::unknown_user("nobody", TEST_PASSWORD)]
+    #[tokio::test]
+    async fn login_invalid_credentials(#[case] username: &str, #[case] password: &str) {
+        let ctx = setup_test_context().await;
+        ctx.server
+            .post("/users")
+            .json(&json!({"username": "root", "name": "Superuser", "password": TEST_PASSWORD}))
+            .await
+            .assert_status(StatusCode::CREATED);
+
+        let response = ctx
+            .server
+            .post("/login")
+            .json(&json!({"username": username, "password": password}))
+            .await;
+
+        response.assert_status(StatusCode::UNAUTHORIZED);
+        response.assert_header(header::WWW_AUTHENTICATE, "Bearer");
+        assert_eq!(
+            "Invalid username or password",
+            response.json::<Value>()["detail"]
+        );
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+}
@@ -1,22 +1,74 @@
 use axum::{
+    handler::Handler,
+    middleware,
     response::{Html, IntoResponse},
-    routing::get,
+    routing::{get, patch, post},
     Extension, Router,
 };
-use blogs_api::{create_blog, delete_blog, get_blog, get_blogs, update_blog};
-use config::{get_db_url, get_postgres_pool};
-use models::{Blog, BlogUpdatePayload};
+use blog_filters::TagMatch;
+use blogs_api::{create_blog, delete_blog, get_blog, get_blogs, patch_blog, update_blog};
+use bulk_api::{export_blogs, import_blogs, ExportFormat};
+use comments_api::{create_comment, delete_comment, get_comments, update_comment};
+use config::{ConfigError, LogFormat, Settings};
+use duplicates_api::{get_duplicates, merge_blog};
+use errors::{problem_instance, ProblemDetails};
+use feeds_api::{atom_feed, json_feed, opml_feed, rss_feed};
+use health_api::{healthz, readyz};
+use idempotency::idempotent;
+use json_patch::Patch;
+use likes_api::{like_blog, unlike_blog};
+use login_api::login;
+use models::{
+    AuthorStats, AuthorsStats, Blog, BlogLikes, BlogMergePayload, BlogPostPayload,
+    BlogReplacePayload, BlogRevision, BlogStats, BlogUpdatePayload, BlogUser, Comment,
+    CommentPostPayload, CommentUpdatePayload, DuplicatePair, FacetCount, FieldChange, HealthCheck,
+    ImportReport, Liveness, LoginPayload, LoginResponse, MigrationsCheck, PoolStats, PurgeReport,
+    Readiness, ReadinessChecks, RevisionAction, SearchFacets, SearchHighlight, SearchHit,
+    SearchResults, Tag, TagMergePayload, TagRenamePayload, TrashedBlog, User, UserBlog,
+    UserPostPayload,
+};
+use revisions_api::{get_revisions, revert_revision};
+use search_api::search_blogs;
+use shutdown::{serve, trigger_on_signal, Shutdown};
 use sqlx::PgPool;
+use stats_api::{get_author_stats, get_stats};
 use std::net::SocketAddr;
-use tracing::{error, info, Level};
-use utoipa::OpenApi;
+use tags_api::{get_tags, merge_tag, rename_tag};
+use tracing::{error, info, warn};
+use trash_api::{get_trash, purge_periodically, purge_trash, restore_blog};
+use users_api::{create_user, get_user, get_users};
+use utoipa::{
+    openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+    Modify, OpenApi,
+};
 use utoipa_swagger_ui::SwaggerUi;
+mod auth;
+mod blog_filters;
 mod blogs_api;
+mod bulk_api;
+mod comments_api;
+mod conditional;
 mod config;
+mod duplicates_api;
 mod errors;
+mod extractors;
+mod feeds_api;
+mod health_api;
+mod idempotency;
+mod likes_api;
+mod login_api;
 mod models;
+mod pagination;
+mod passwords;
+mod revisions_api;
+mod search_api;
+mod shutdown;
+mod stats_api;
+mod tags_api;
 #[cfg(test)]
 mod test_helper;
+mod trash_api;
+mod users_api;
 
 #[derive(OpenApi)]
 #[openapi(
@@ -24,50 +76,273 @@ mod test_helper;
         blogs_api::get_blogs,
         blogs_api::get_blog,
         blogs_api::update_blog,
+        blogs_api::patch_blog,
         blogs_api::delete_blog,
-        blogs_api::create_blog
+        blogs_api::create_blog,
+        trash_api::get_trash,
+        trash_api::restore_blog,
+        trash_api::purge_trash,
+        bulk_api::import_blogs,
+        bulk_api::export_blogs,
+        duplicates_api::get_duplicates,
+        duplicates_api::merge_blog,
+        revisions_api::get_revisions,
+        revisions_api::revert_revision,
+        likes_api::like_blog,
+        likes_api::unlike_blog,
+        comments_api::get_comments,
+        comments_api::create_comment,
+        comments_api::update_comment,
+        comments_api::delete_comment,
+        search_api::search_blogs,
+        feeds_api::rss_feed,
+        feeds_api::atom_feed,
+        feeds_api::json_feed,
+        feeds_api::opml_feed,
+        stats_api::get_stats,
+        stats_api::get_author_stats,
+        tags_api::get_tags,
+        tags_api::rename_tag,
+        tags_api::merge_tag,
+        users_api::create_user,
+        users_api::get_users,
+        users_api::get_user,
+        login_api::login,
+        health_api::healthz,
+        health_api::readyz
     ),
     components(
-        schemas(Blog,  BlogUpdatePayload)
+        schemas(
+            Blog,
+            BlogLikes,
+            BlogPostPayload,
+            BlogReplacePayload,
+            BlogUpdatePayload,
+            BlogUser,
+            BlogRevision,
+            FieldChange,
+            RevisionAction,
+            Comment,
+            ExportFormat,
+            ImportReport,
+            TrashedBlog,
+            PurgeReport,
+            DuplicatePair,
+            BlogMergePayload,
+            CommentPostPayload,
+            CommentUpdatePayload,
+            Patch,
+            ProblemDetails,
+            SearchResults,
+            SearchHit,
+            SearchHighlight,
+            SearchFacets,
+            FacetCount,
+            BlogStats,
+            AuthorsStats,
+            AuthorStats,
+            Tag,
+            TagMatch,
+            TagMergePayload,
+            TagRenamePayload,
+            LoginPayload,
+            LoginResponse,
+            User,
+            UserBlog,
+            UserPostPayload,
+            Liveness,
+            Readiness,
+            ReadinessChecks,
+            HealthCheck,
+            MigrationsCheck,
+            PoolStats
+        )
     ),
     tags(
-        (name = "blogs_api", description = "Blog management API")
-    )
+        (name = "blogs_api", description = "Blog management API"),
+        (name = "trash_api", description = "Deleted blogs, until they are restored or purged"),
+        (name = "bulk_api", description = "Import and export of many blogs at once"),
+        (name = "revisions_api", description = "History of the changes of blogs"),
+        (name = "duplicates_api", description = "Blogs submitted more than once and merging them"),
+        (name = "likes_api", description = "Likes of blogs, one per caller"),
+        (name = "comments_api", description = "Comments on blogs and replies to them"),
+        (name = "search_api", description = "Full-text search over blogs"),
+        (name = "feeds_api", description = "Feeds of the newest blogs for feed readers"),
+        (name = "stats_api", description = "Statistics over all blogs"),
+        (name = "tags_api", description = "Tags labelling blogs"),
+        (name = "users_api", description = "User accounts"),
+        (name = "login_api", description = "Tokens for the routes that need a user"),
+        (name = "health_api", description = "Probes for the orchestrator running the server")
+    ),
+    modifiers(&SecurityAddon)
 )]
 struct ApiDoc;
 
+/// Declares the `bearer` scheme used by the routes that need a user
+struct SecurityAddon;
+
+impl Modify for SecurityAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "bearer",
+                SecurityScheme::Http(
+                    HttpBuilder::new()
+                        .scheme(HttpAuthScheme::Bearer)
+                        .bearer_format("JWT")
+                        .description(Some("Token returned by `POST /login`"))
+                        .build(),
+                ),
+            );
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let settings = match Settings::load() {
+        Ok(settings) => settings,
+        Err(ConfigError::Help(usage)) => {
+            println!("{usage}");
+            return Ok(());
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(2);
+        }
+    };
+
     // for logging
-    tracing_subscriber::fmt().with_max_level(Level::INFO).init();
+    let subscriber = tracing_subscriber::fmt().with_max_level(settings.log.level());
+    match settings.log.format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().init(),
+    }
 
-    let db_url = get_db_url(false);
-    let pool = get_postgres_pool(db_url).await;
-    let app = app(pool).await;
+    let pool = settings.database.connect().await.map_err(|e| {
+        error!("Failed to connect to the database: {}", e);
+        e
+    })?;
+    info!("Connected to Database.");
+    let shutdown = Shutdown::default();
+    let tasks = [
+        tokio::spawn(purge_periodically(
+            pool.clone(),
+            settings.trash_config(),
+            shutdown.clone(),
+        )),
+        tokio::spawn(idempotency::purge_periodically(
+            pool.clone(),
+            shutdown.clone(),
+        )),
+    ];
+    let app = app(pool.clone(), &settings, shutdown.clone()).await;
 
     // starting the server
-    let addr = SocketAddr::from(([127, 0, 0, 1], 8080));
+    let addr = SocketAddr::from((settings.server.host, settings.server.port));
     let listener = tokio::net::TcpListener::bind(&addr).await.map_err(|e| {
         error!("Failed to bind to address: {} ", e);
         e
     })?;
     info!("Application running at {}", &addr);
-    axum::serve(listener, app.into_make_service()).await?;
+    tokio::spawn(trigger_on_signal(shutdown.clone()));
+    serve(
+        listener,
+        app,
+        shutdown,
+        settings.server.shutdown_delay(),
+        settings.server.shutdown_timeout(),
+    )
+    .await?;
+
+    // the background tasks finish what they are doing before returning
+    for task in tasks {
+        task.await?;
+    }
+    // waits for the connections still used by abandoned requests, but not forever
+    if tokio::time::timeout(settings.server.shutdown_timeout(), pool.close())
+        .await
+        .is_err()
+    {
+        warn!("Database connections still in use, exiting without closing them");
+    }
+    info!("Shut down.");
 
     Ok(())
 }
 
-async fn app(pool: PgPool) -> Router {
-    Router::new()
+async fn app(pool: PgPool, settings: &Settings, shutdown: Shutdown) -> Router {
+    let idempotent = || middleware::from_fn(idempotent);
+
+    let mut router = Router::new()
         .without_v07_checks()
         .route("/", get(index))
         .route(
             "/blogs/{id}",
-            get(get_blog).put(update_blog).delete(delete_blog),
+            get(get_blog)
+                .put(update_blog)
+                .patch(patch_blog)
+                .delete(delete_blog),
+        )
+        .route(
+            "/blogs",
+            get(get_blogs).post(create_blog.layer(idempotent())),
+        )
+        .route("/blogs/{id}/restore", post(restore_blog))
+        .route("/blogs/{id}/revisions", get(get_revisions))
+        .route("/blogs/{id}/revisions/{rev}/revert", post(revert_revision))
+        .route("/trash", get(get_trash))
+        .route("/trash/purge", post(purge_trash))
+        .route(
+            "/blogs/{id}/like",
+            post(like_blog.layer(idempotent())).delete(unlike_blog),
+        )
+        .route(
+            "/blogs/{id}/comments",
+            get(get_comments).post(create_comment.layer(idempotent())),
+        )
+        .route(
+            "/comments/{id}",
+            patch(update_comment).delete(delete_comment),
         )
-        .route("/blogs", get(get_blogs).post(create_blog))
-        .merge(SwaggerUi::new("/api-docs").url("/api-docs/openapi.json", ApiDoc::openapi()))
+        .route("/blogs/search", get(search_blogs))
+        .route("/blogs/import", post(import_blogs))
+        .route("/blogs/export", get(export_blogs))
+        .route("/blogs/duplicates", get(get_duplicates))
+        .route("/blogs/{id}/merge", post(merge_blog))
+        .route("/feed.rss", get(rss_feed))
+        .route("/feed.atom", get(atom_feed))
+        .route("/feed.json", get(json_feed))
+        .route("/feed.opml", get(opml_feed))
+        .route("/stats", get(get_stats))
+        .route("/stats/authors", get(get_author_stats))
+        .route("/tags", get(get_tags))
+        .route("/tags/{name}", patch(rename_tag))
+        .route("/tags/{name}/merge", post(merge_tag))
+        .route("/users", get(get_users).post(create_user))
+        .route("/users/{id}", get(get_user))
+        .route("/login", post(login))
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz));
+    if settings.features.swagger_ui {
+        router = router
+            .merge(SwaggerUi::new("/api-docs").url("/api-docs/openapi.json", ApiDoc::openapi()));
+    }
+    router = router
+        .layer(middleware::from_fn(problem_instance))
         .layer(Extension(pool))
+        .layer(Extension(shutdown))
+        .layer(Extension(settings.auth_config()))
+        .layer(Extension(settings.trash_config()))
+        .layer(Extension(settings.precondition_config()))
+        .layer(Extension(settings.idempotency_config()))
+        .layer(Extension(settings.feed_config()));
+    // outermost, so preflight requests are answered before anything else runs
+    if let Some(cors) = settings.server.cors() {
+        router = router.layer(cors);
+    }
+
+    router
 }
 
 async fn index() -> impl IntoResponse {
@@ -76,7 +351,6 @@ async fn index() -> impl IntoResponse {
 
 // test('blog without likes field defaults to zero', async () => {
 // test('a new blog can be correctly added via POST', async () => {
-// test('a new blog cannot be added if a valid token is not provided', async () => {
 // test('missing url or title in POST request should return status code 400', async () => {
 // #[rstest]
 // #[tokio::test]
@@ -118,16 +392,3 @@ async fn index() -> impl IntoResponse {
 //     //
 //     // error when id is wrong
 // }
-
-//     #[rstest]
-//     #[tokio::test]
-// test('a blog can be correctly updated via PUT', async () =>
-//     async fn test_update_blogs() {
-//         let client = get_test_client().await;
-//         let server = get_test_server().await;
-
-//         // update blog with some params
-//         // update blog with no params doesnt change anyuthing
-//         // error when id is wrong
-//     }
-// }
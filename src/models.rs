@@ -1,30 +1,560 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, FromRow};
 use utoipa::ToSchema;
+use validator::{Validate, ValidationError};
 
-#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub const TITLE_MAX_LENGTH: u64 = 300;
+pub const AUTHOR_MAX_LENGTH: u64 = 200;
+pub const URL_MAX_LENGTH: u64 = 2048;
+pub const USERNAME_MIN_LENGTH: u64 = 3;
+pub const USERNAME_MAX_LENGTH: u64 = 50;
+pub const NAME_MAX_LENGTH: u64 = 200;
+pub const PASSWORD_MIN_LENGTH: u64 = 8;
+pub const PASSWORD_MAX_LENGTH: u64 = 128;
+pub const COMMENT_MAX_LENGTH: u64 = 5000;
+pub const TAG_MAX_LENGTH: usize = 50;
+pub const TAGS_PER_BLOG: u64 = 10;
+
+#[derive(Serialize, Deserialize, ToSchema, FromRow, Debug)]
 pub struct Blog {
-    pub id: i64,
+    pub id: i32,
     pub title: String,
     pub author: String,
     pub url: String,
+    /// Tags of the blog, lowercase and sorted
+    pub tags: Vec<String>,
+    /// Number of likes, changed through `/blogs/{id}/like`
     pub likes: i32,
+    /// Number of comments on the blog, replies included
+    pub comment_count: i32,
+    /// When the blog was added, RFC 3339
+    pub created_at: DateTime<Utc>,
+    /// When the blog was last changed, RFC 3339
+    pub updated_at: DateTime<Utc>,
+    /// Goes up with every change of the blog, sent as its `ETag`
+    #[serde(skip)]
+    pub version: i32,
+    /// User who submitted the blog, `null` for blogs added before there were users
+    #[schema(value_type = Option<BlogUser>)]
+    pub user: Option<Json<BlogUser>>,
 }
+/// Public part of the user who submitted a blog
 #[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct BlogUser {
+    pub id: i32,
+    pub username: String,
+    pub name: String,
+}
+#[derive(Serialize, Deserialize, ToSchema, Validate, Debug)]
 pub struct BlogPostPayload {
+    #[validate(
+        custom(function = "not_blank"),
+        length(max = "TITLE_MAX_LENGTH", message = "must be at most 300 characters")
+    )]
+    #[schema(max_length = 300)]
     pub title: String,
+    #[validate(
+        custom(function = "not_blank"),
+        length(max = "AUTHOR_MAX_LENGTH", message = "must be at most 200 characters")
+    )]
+    #[schema(max_length = 200)]
     pub author: String,
+    #[validate(
+        custom(function = "http_url"),
+        length(max = "URL_MAX_LENGTH", message = "must be at most 2048 characters")
+    )]
+    #[schema(max_length = 2048)]
     pub url: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub likes: Option<i32>,
+    /// Tags of the blog, normalized to lowercase, duplicates are dropped
+    #[serde(default)]
+    #[validate(
+        length(max = "TAGS_PER_BLOG", message = "must have at most 10 tags"),
+        custom(function = "tag_names")
+    )]
+    #[schema(max_items = 10, example = json!(["rust", "testing"]))]
+    pub tags: Vec<String>,
 }
-#[derive(Serialize, Deserialize, ToSchema, Debug)]
+/// Full replacement of a blog, used by PUT
+#[derive(Serialize, Deserialize, ToSchema, Validate, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct BlogReplacePayload {
+    #[validate(
+        custom(function = "not_blank"),
+        length(max = "TITLE_MAX_LENGTH", message = "must be at most 300 characters")
+    )]
+    #[schema(max_length = 300)]
+    pub title: String,
+    #[validate(
+        custom(function = "not_blank"),
+        length(max = "AUTHOR_MAX_LENGTH", message = "must be at most 200 characters")
+    )]
+    #[schema(max_length = 200)]
+    pub author: String,
+    #[validate(
+        custom(function = "http_url"),
+        length(max = "URL_MAX_LENGTH", message = "must be at most 2048 characters")
+    )]
+    #[schema(max_length = 2048)]
+    pub url: String,
+    /// Tags of the blog, normalized to lowercase, duplicates are dropped
+    #[serde(default)]
+    #[validate(
+        length(max = "TAGS_PER_BLOG", message = "must have at most 10 tags"),
+        custom(function = "tag_names")
+    )]
+    #[schema(max_items = 10, example = json!(["rust", "testing"]))]
+    pub tags: Vec<String>,
+}
+/// Merge patch of a blog, used by PATCH: absent fields are left untouched
+#[derive(Serialize, Deserialize, ToSchema, Validate, Debug)]
+#[serde(deny_unknown_fields)]
 pub struct BlogUpdatePayload {
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[validate(
+        custom(function = "not_blank"),
+        length(max = "TITLE_MAX_LENGTH", message = "must be at most 300 characters")
+    )]
+    #[schema(max_length = 300)]
     pub title: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[validate(
+        custom(function = "not_blank"),
+        length(max = "AUTHOR_MAX_LENGTH", message = "must be at most 200 characters")
+    )]
+    #[schema(max_length = 200)]
     pub author: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[validate(
+        custom(function = "http_url"),
+        length(max = "URL_MAX_LENGTH", message = "must be at most 2048 characters")
+    )]
+    #[schema(max_length = 2048)]
     pub url: Option<String>,
+    /// Replaces all the tags of the blog
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[validate(
+        length(max = "TAGS_PER_BLOG", message = "must have at most 10 tags"),
+        custom(function = "tag_names")
+    )]
+    #[schema(max_items = 10)]
+    pub tags: Option<Vec<String>>,
+}
+
+/// Likes of a blog after a like or unlike
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct BlogLikes {
+    pub blog_id: i32,
+    /// Number of likes of the blog
+    pub likes: i32,
+    /// Whether the caller likes the blog
+    pub liked: bool,
+}
+
+/// A registered user and the blogs they submitted
+#[derive(Serialize, Deserialize, ToSchema, FromRow, Debug)]
+pub struct User {
+    pub id: i32,
+    pub username: String,
+    pub name: String,
+    /// When the user registered, RFC 3339
+    pub created_at: DateTime<Utc>,
+    #[schema(value_type = Vec<UserBlog>)]
+    pub blogs: Json<Vec<UserBlog>>,
+}
+/// A blog embedded in its user
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct UserBlog {
+    pub id: i32,
+    pub title: String,
+    pub author: String,
+    pub url: String,
+    pub likes: i32,
+}
+/// Registration of a new user
+#[derive(Deserialize, ToSchema, Validate, Debug)]
+pub struct UserPostPayload {
+    #[validate(
+        length(
+            min = "USERNAME_MIN_LENGTH",
+            max = "USERNAME_MAX_LENGTH",
+            message = "must be between 3 and 50 characters"
+        ),
+        custom(function = "username_chars")
+    )]
+    #[schema(min_length = 3, max_length = 50, pattern = "^[A-Za-z0-9_.-]+$")]
+    pub username: String,
+    #[validate(
+        custom(function = "not_blank"),
+        length(max = "NAME_MAX_LENGTH", message = "must be at most 200 characters")
+    )]
+    #[schema(max_length = 200)]
+    pub name: String,
+    #[validate(length(
+        min = "PASSWORD_MIN_LENGTH",
+        max = "PASSWORD_MAX_LENGTH",
+        message = "must be between 8 and 128 characters"
+    ))]
+    #[schema(min_length = 8, max_length = 128, format = Password)]
+    pub password: String,
+}
+
+/// Outcome of a bulk import
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct ImportReport {
+    /// Whether the rows were only validated
+    pub dry_run: bool,
+    /// Number of valid rows, all of them are imported unless this is a dry run
+    pub rows: usize,
+    /// Ids of the imported blogs in the order of the rows, empty on a dry run
+    pub ids: Vec<i32>,
+}
+
+/// A deleted blog waiting in the trash
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct TrashedBlog {
+    #[serde(flatten)]
+    pub blog: Blog,
+    pub deleted_at: DateTime<Utc>,
+    /// When the blog is removed for good unless it is restored before
+    pub purge_at: DateTime<Utc>,
+}
+
+/// What a revision did to a blog
+#[derive(Serialize, Deserialize, ToSchema, sqlx::Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[sqlx(type_name = "revision_action", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum RevisionAction {
+    Create,
+    Update,
+    Delete,
+    Restore,
+    Revert,
+}
+
+/// A change of a blog
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct BlogRevision {
+    /// Number of the revision, counting from 1 for each blog
+    pub rev: i32,
+    pub action: RevisionAction,
+    /// User who made the change, `null` when unknown
+    #[schema(value_type = Option<BlogUser>)]
+    pub user: Option<Json<BlogUser>>,
+    /// When the change was made, RFC 3339
+    pub created_at: DateTime<Utc>,
+    /// Fields the change touched, `null` values stand for a blog that did not exist or was
+    /// deleted
+    pub changes: Vec<FieldChange>,
+}
+
+/// The value of a field of a blog before and after a change
+#[derive(Serialize, Deserialize, ToSchema, Debug, PartialEq)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: serde_json::Value,
+    pub new: serde_json::Value,
+}
+
+/// Outcome of purging the trash
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct PurgeReport {
+    /// Number of blogs removed for good
+    pub purged: u64,
+}
+
+/// The process is up and answering
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct Liveness {
+    pub alive: bool,
+}
+
+/// Whether the server can take requests, with the outcome of each check
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct Readiness {
+    /// `true` when every check passed
+    pub ready: bool,
+    pub checks: ReadinessChecks,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct ReadinessChecks {
+    /// Fails as soon as the server starts shutting down
+    pub shutdown: HealthCheck,
+    /// Fails when a trivial query does not answer in time
+    pub database: HealthCheck,
+    pub migrations: MigrationsCheck,
+    /// Connections of the pool when the check ran, informative only
+    pub pool: PoolStats,
+}
+
+/// Outcome of one readiness check
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct HealthCheck {
+    pub ok: bool,
+    /// Why the check failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+/// Whether the database has every migration shipped with the server
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct MigrationsCheck {
+    pub ok: bool,
+    /// Why the check failed
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub likes: Option<i32>,
+    pub detail: Option<String>,
+    /// Number of migrations shipped with the server
+    pub expected: usize,
+    /// Number of them applied to the database
+    pub applied: usize,
+    /// Versions shipped with the server but not applied, or whose last run failed
+    pub pending: Vec<i64>,
+    /// Versions applied from a file that has changed since
+    pub changed: Vec<i64>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct PoolStats {
+    /// Open connections, idle or in use
+    pub size: u32,
+    pub idle: usize,
+    pub max_connections: u32,
+}
+
+/// Two blogs outside the trash that are likely the same article
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct DuplicatePair {
+    /// The blog submitted first
+    #[schema(value_type = UserBlog)]
+    pub blog: Json<UserBlog>,
+    /// The blog submitted later, which can be merged into the first one
+    #[schema(value_type = UserBlog)]
+    pub duplicate: Json<UserBlog>,
+    /// Trigram similarity of the titles, from 0 to 1
+    pub title_similarity: f32,
+    /// Whether the urls only differ in their query parameters
+    pub same_page: bool,
+}
+
+/// Blog that takes over the likes, comments and tags of the merged one
+#[derive(Serialize, Deserialize, ToSchema, Validate, Debug)]
+pub struct BlogMergePayload {
+    pub into: i32,
+}
+
+/// Totals over all blogs and the most liked one
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct BlogStats {
+    /// Number of blogs
+    pub blogs: i64,
+    /// Sum of the likes of all blogs
+    pub total_likes: i64,
+    /// Blog with the most likes, `null` when there are no blogs
+    pub favorite_blog: Option<Blog>,
+}
+/// The authors that stand out
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct AuthorsStats {
+    /// Number of distinct authors
+    pub authors: i64,
+    /// Author with the most blogs, `null` when there are no blogs
+    pub most_blogs: Option<AuthorStats>,
+    /// Author with the most likes over all their blogs, `null` when there are no blogs
+    pub most_likes: Option<AuthorStats>,
+}
+/// Number of blogs and likes of an author
+#[derive(Serialize, Deserialize, ToSchema, FromRow, Debug)]
+pub struct AuthorStats {
+    pub author: String,
+    pub blogs: i64,
+    pub likes: i64,
+}
+
+/// A tag and the number of blogs using it
+#[derive(Serialize, Deserialize, ToSchema, FromRow, Debug)]
+pub struct Tag {
+    pub name: String,
+    pub count: i64,
+}
+/// New name of a tag
+#[derive(Serialize, Deserialize, ToSchema, Validate, Debug)]
+pub struct TagRenamePayload {
+    #[validate(custom(function = "tag_name"))]
+    #[schema(max_length = 50)]
+    pub name: String,
+}
+/// Tag that takes over the blogs of the merged one
+#[derive(Serialize, Deserialize, ToSchema, Validate, Debug)]
+pub struct TagMergePayload {
+    #[validate(custom(function = "tag_name"))]
+    #[schema(max_length = 50)]
+    pub into: String,
+}
+
+/// A comment on a blog, replies point to the comment they answer
+#[derive(Serialize, Deserialize, ToSchema, FromRow, Debug)]
+pub struct Comment {
+    pub id: i32,
+    pub blog_id: i32,
+    /// Comment this one replies to, `null` for top level comments
+    pub parent_id: Option<i32>,
+    pub body: String,
+    /// When the comment was written, RFC 3339
+    pub created_at: DateTime<Utc>,
+    /// When the comment was last edited, RFC 3339
+    pub updated_at: DateTime<Utc>,
+    /// User who wrote the comment
+    #[schema(value_type = BlogUser)]
+    pub user: Json<BlogUser>,
+}
+/// A new comment or reply
+#[derive(Serialize, Deserialize, ToSchema, Validate, Debug)]
+pub struct CommentPostPayload {
+    #[validate(
+        custom(function = "not_blank"),
+        length(
+            max = "COMMENT_MAX_LENGTH",
+            message = "must be at most 5000 characters"
+        )
+    )]
+    #[schema(max_length = 5000)]
+    pub body: String,
+    /// Comment of the same blog to reply to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<i32>,
+}
+/// New text of a comment, used by PATCH
+#[derive(Serialize, Deserialize, ToSchema, Validate, Debug)]
+pub struct CommentUpdatePayload {
+    #[validate(
+        custom(function = "not_blank"),
+        length(
+            max = "COMMENT_MAX_LENGTH",
+            message = "must be at most 5000 characters"
+        )
+    )]
+    #[schema(max_length = 5000)]
+    pub body: String,
+}
+
+/// Credentials of a user, not `Debug` so that passwords cannot end up in logs
+#[derive(Deserialize, ToSchema)]
+pub struct LoginPayload {
+    pub username: String,
+    #[schema(format = Password)]
+    pub password: String,
+}
+/// Token to send as `Authorization: Bearer <token>`
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct LoginResponse {
+    pub token: String,
+    /// When the token stops being accepted, RFC 3339
+    pub expires_at: DateTime<Utc>,
+    pub username: String,
+    pub name: String,
+}
+
+/// A blog matching a full-text search
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct SearchHit {
+    #[serde(flatten)]
+    pub blog: Blog,
+    /// Relevance of the blog for the query, higher is better
+    pub rank: f32,
+    /// Title and author escaped as HTML text, with the matching words wrapped in `<mark>` tags
+    pub highlight: SearchHighlight,
+}
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct SearchHighlight {
+    pub title: String,
+    pub author: String,
+}
+/// Number of matching blogs sharing a value
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct FacetCount {
+    pub value: String,
+    pub count: i64,
+}
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct SearchFacets {
+    pub authors: Vec<FacetCount>,
+    pub domains: Vec<FacetCount>,
+}
+#[derive(Serialize, Deserialize, ToSchema, Debug)]
+pub struct SearchResults {
+    /// Total number of matching blogs
+    pub total: i64,
+    /// Page of matching blogs, most relevant first
+    pub results: Vec<SearchHit>,
+    pub facets: SearchFacets,
+}
+
+/// The value must contain something other than whitespace
+fn not_blank(value: &str) -> Result<(), ValidationError> {
+    if value.trim().is_empty() {
+        return Err(ValidationError::new("blank").with_message("must not be blank".into()));
+    }
+    Ok(())
+}
+
+/// Tags end up in urls, keep them short and to a safe set of characters
+fn tag_name(value: &str) -> Result<(), ValidationError> {
+    let value = value.trim();
+    if value.is_empty() || value.chars().count() > TAG_MAX_LENGTH {
+        return Err(
+            ValidationError::new("tag").with_message("must be between 1 and 50 characters".into())
+        );
+    }
+    if !value
+        .chars()
+        .all(|c| c.is_alphanumeric() || matches!(c, '_' | '.' | '-' | '+' | '#'))
+    {
+        return Err(ValidationError::new("tag")
+            .with_message("must only contain letters, digits, `_`, `.`, `-`, `+` and `#`".into()));
+    }
+    Ok(())
+}
+
+fn tag_names(values: &[String]) -> Result<(), ValidationError> {
+    values.iter().try_for_each(|value| tag_name(value))
+}
+
+/// Trimmed, lowercase tags without duplicates, in their original order
+pub fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = normalize_tag(tag);
+        if !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    normalized
+}
+
+pub fn normalize_tag(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
+
+/// Usernames end up in urls and logs, keep them to a safe set of characters
+fn username_chars(value: &str) -> Result<(), ValidationError> {
+    if !value
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+    {
+        return Err(ValidationError::new("username")
+            .with_message("must only contain letters, digits, `_`, `.` and `-`".into()));
+    }
+    Ok(())
+}
+
+/// The value must be an absolute http or https url
+fn http_url(value: &str) -> Result<(), ValidationError> {
+    match url::Url::parse(value) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") && url.has_host() => Ok(()),
+        _ => {
+            Err(ValidationError::new("url")
+                .with_message("must be a valid http or https url".into()))
+        }
+    }
 }
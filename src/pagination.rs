@@ -0,0 +1,238 @@
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, Uri};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::IntoParams;
+
+use crate::errors::AppError;
+
+pub const DEFAULT_PAGE_SIZE: i64 = 20;
+pub const MAX_PAGE_SIZE: i64 = 100;
+
+pub const TOTAL_COUNT_HEADER: HeaderName = HeaderName::from_static("x-total-count");
+
+/// Query parameters selecting a page of a list
+#[derive(Deserialize, IntoParams, Debug, Default)]
+#[into_params(parameter_in = Query)]
+pub struct PageParams {
+    /// Maximum number of items in the page
+    #[param(minimum = 1, maximum = 100, default = 20)]
+    pub limit: Option<i64>,
+    /// Number of items to skip, cannot be combined with `cursor`
+    #[param(minimum = 0)]
+    pub offset: Option<i64>,
+    /// Opaque cursor taken from the `Link` header of another page
+    pub cursor: Option<String>,
+}
+
+/// Where a page starts
+#[derive(Debug, Clone, PartialEq)]
+pub enum PageMode {
+    /// Skip a number of items
+    Offset(i64),
+    /// Keyset pagination from the start of the list
+    Start,
+    /// Items right after the one with these sort key values
+    After(Vec<Value>),
+    /// Items right before the one with these sort key values
+    Before(Vec<Value>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Page {
+    pub limit: i64,
+    pub mode: PageMode,
+}
+
+/// A page of items and where the neighbouring pages start
+pub struct PageResult<T> {
+    pub items: Vec<T>,
+    pub prev: Option<PageMode>,
+    pub next: Option<PageMode>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Cursor {
+    #[serde(rename = "b")]
+    before: bool,
+    #[serde(rename = "k")]
+    keys: Vec<Value>,
+}
+
+impl PageParams {
+    pub fn page(&self) -> Result<Page, AppError> {
+        let limit = self.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+        if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+            return Err(AppError::invalid_field(
+                "Invalid query parameter",
+                "limit",
+                format!("must be between 1 and {MAX_PAGE_SIZE}"),
+            ));
+        }
+
+        let mode = match (self.offset, &self.cursor) {
+            (Some(_), Some(_)) => {
+                return Err(AppError::BadRequest(
+                    "`offset` and `cursor` cannot be used together".to_string(),
+                ))
+            }
+            (Some(offset), None) if offset < 0 => {
+                return Err(AppError::invalid_field(
+                    "Invalid query parameter",
+                    "offset",
+                    "must not be negative".to_string(),
+                ))
+            }
+            (Some(offset), None) => PageMode::Offset(offset),
+            (None, Some(cursor)) => decode_cursor(cursor)?,
+            (None, None) => PageMode::Start,
+        };
+
+        Ok(Page { limit, mode })
+    }
+}
+
+impl PageParams {
+    /// Like `page`, for lists that can only be paged by offset
+    pub fn offset_page(&self) -> Result<Page, AppError> {
+        if self.cursor.is_some() {
+            return Err(AppError::invalid_field(
+                "Invalid query parameter",
+                "cursor",
+                "is not supported here, use `offset`".to_string(),
+            ));
+        }
+        let page = self.page()?;
+        Ok(Page {
+            mode: PageMode::Offset(self.offset.unwrap_or(0)),
+            ..page
+        })
+    }
+}
+
+impl Page {
+    /// Number of rows to fetch, one more than the limit tells whether there are more
+    pub fn fetch_limit(&self) -> i64 {
+        self.limit + 1
+    }
+
+    /// Whether rows have to be fetched in reverse order, `finish` restores the order
+    pub fn is_backwards(&self) -> bool {
+        matches!(self.mode, PageMode::Before(_))
+    }
+
+    /// Turns the rows fetched for this page into the page and its neighbours
+    ///
+    /// `keys` returns the values of the sort keys of a row, they end up in the cursors
+    pub fn finish<T>(&self, mut rows: Vec<T>, keys: impl Fn(&T) -> Vec<Value>) -> PageResult<T> {
+        let has_more = rows.len() as i64 > self.limit;
+        rows.truncate(self.limit as usize);
+        if self.is_backwards() {
+            rows.reverse();
+        }
+
+        let first = rows.first().map(&keys);
+        let last = rows.last().map(&keys);
+        let (prev, next) = match &self.mode {
+            &PageMode::Offset(offset) => (
+                (offset > 0).then(|| PageMode::Offset((offset - self.limit).max(0))),
+                has_more.then(|| PageMode::Offset(offset + self.limit)),
+            ),
+            PageMode::Start => (None, last.filter(|_| has_more).map(PageMode::After)),
+            PageMode::After(_) => (
+                first.map(PageMode::Before),
+                last.filter(|_| has_more).map(PageMode::After),
+            ),
+            PageMode::Before(_) => (
+                first.filter(|_| has_more).map(PageMode::Before),
+                last.map(PageMode::After),
+            ),
+        };
+
+        PageResult {
+            items: rows,
+            prev,
+            next,
+        }
+    }
+}
+
+impl<T> PageResult<T> {
+    /// `Link` and `X-Total-Count` headers for this page of the list at `uri`
+    pub fn headers(&self, uri: &Uri, limit: i64, total: i64) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(TOTAL_COUNT_HEADER, HeaderValue::from(total));
+
+        let links: Vec<String> = [("prev", &self.prev), ("next", &self.next)]
+            .into_iter()
+            .filter_map(|(rel, mode)| {
+                let mode = mode.as_ref()?;
+                Some(format!("<{}>; rel=\"{rel}\"", page_uri(uri, limit, mode)))
+            })
+            .collect();
+        if !links.is_empty() {
+            if let Ok(link) = HeaderValue::from_str(&links.join(", ")) {
+                headers.insert(header::LINK, link);
+            }
+        }
+
+        headers
+    }
+}
+
+/// `uri` with its paging parameters replaced by the ones of `mode`
+fn page_uri(uri: &Uri, limit: i64, mode: &PageMode) -> String {
+    let mut query = url::form_urlencoded::Serializer::new(String::new());
+    url::form_urlencoded::parse(uri.query().unwrap_or_default().as_bytes())
+        .filter(|(key, _)| !matches!(key.as_ref(), "limit" | "offset" | "cursor"))
+        .for_each(|(key, value)| {
+            query.append_pair(&key, &value);
+        });
+    query.append_pair("limit", &limit.to_string());
+    match mode {
+        PageMode::Offset(offset) => {
+            query.append_pair("offset", &offset.to_string());
+        }
+        PageMode::After(keys) => {
+            query.append_pair("cursor", &encode_cursor(false, keys));
+        }
+        PageMode::Before(keys) => {
+            query.append_pair("cursor", &encode_cursor(true, keys));
+        }
+        PageMode::Start => {}
+    }
+
+    format!("{}?{}", uri.path(), query.finish())
+}
+
+fn encode_cursor(before: bool, keys: &[Value]) -> String {
+    let cursor = serde_json::to_vec(&Cursor {
+        before,
+        keys: keys.to_vec(),
+    })
+    .unwrap_or_default();
+    URL_SAFE_NO_PAD.encode(cursor)
+}
+
+fn decode_cursor(cursor: &str) -> Result<PageMode, AppError> {
+    let cursor: Cursor = URL_SAFE_NO_PAD
+        .decode(cursor)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .ok_or_else(invalid_cursor)?;
+
+    Ok(if cursor.before {
+        PageMode::Before(cursor.keys)
+    } else {
+        PageMode::After(cursor.keys)
+    })
+}
+
+/// The cursor could not be decoded or does not fit the requested list
+pub fn invalid_cursor() -> AppError {
+    AppError::invalid_field(
+        "Invalid query parameter",
+        "cursor",
+        "is not a valid cursor".to_string(),
+    )
+}
@@ -0,0 +1,34 @@
+use argon2::{
+    password_hash::{rand_core::OsRng, SaltString},
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+};
+
+use crate::errors::AppError;
+
+/// Argon2id hash of a password in PHC string format, salt and parameters included
+///
+/// Hashing is slow on purpose, it runs on the blocking thread pool
+pub async fn hash_password(password: String) -> Result<String, AppError> {
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|e| AppError::Internal(format!("Failed to hash password: {e}")))
+    })
+    .await
+    .map_err(|e| AppError::Internal(e.to_string()))?
+}
+
+/// Whether a password matches a hash made by `hash_password`
+pub async fn verify_password(password: String, hash: String) -> Result<bool, AppError> {
+    tokio::task::spawn_blocking(move || {
+        let hash = PasswordHash::new(&hash)
+            .map_err(|e| AppError::Internal(format!("Invalid password hash: {e}")))?;
+        Ok(Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok())
+    })
+    .await
+    .map_err(|e| AppError::Internal(e.to_string()))?
+}
@@ -0,0 +1,457 @@
+use axum::{
+    http::{StatusCode, Uri},
+    response::IntoResponse,
+    Extension, Json,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use sqlx::{types::Json as SqlJson, PgConnection, PgPool};
+use validator::Validate;
+
+use crate::{
+    auth::{AuthUser, BlogOwner},
+    blogs_api::{blog_headers, blog_not_found, check_if_match, replace_blog},
+    conditional::IfMatch,
+    errors::{AppError, ProblemDetails},
+    extractors::{deserialize_json_value, AppPath, AppQuery},
+    models::{Blog, BlogReplacePayload, BlogRevision, BlogUser, FieldChange, RevisionAction},
+    pagination::PageParams,
+};
+
+/// Fields of a blog kept in its revisions, as built by the `blog_values` SQL function
+const REVISION_FIELDS: &[&str] = &["title", "author", "url", "tags"];
+
+#[derive(Deserialize)]
+pub struct RevisionPath {
+    rev: i32,
+}
+
+/// Get the revisions of a blog
+///
+/// Returns a page of the changes of a blog given the id, newest first. Each revision tells who
+/// made it, when, and the old and new value of every field it touched. The total number of
+/// revisions is returned in `X-Total-Count`
+#[utoipa::path(
+    get,
+    path = "/blogs/{id}/revisions",
+    params(("id" = i32, Path, description = "Blog id"), PageParams),
+    responses(
+        (status = 500, description = "Internal server error", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 404, description = "Blog not found", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 400, description = "Invalid blog id or paging parameters", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 200, description = "Revisions retrieved successfully", body=[BlogRevision],
+            headers(
+                ("X-Total-Count" = i64, description = "Total number of revisions of the blog"),
+                ("Link" = String, description = "Links to the `prev` and `next` pages")
+            )
+        )
+    )
+)]
+pub async fn get_revisions(
+    Extension(pool): Extension<PgPool>,
+    uri: Uri,
+    AppPath(id): AppPath<i32>,
+    AppQuery(params): AppQuery<PageParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let page = params.offset_page()?;
+    let total = sqlx::query_scalar!(
+        r#"SELECT (SELECT COUNT(*) FROM blog_revisions WHERE blog_id = blogs.id) AS "count!"
+        FROM blogs WHERE id = $1 AND deleted_at IS NULL"#,
+        id
+    )
+    .fetch_optional(&pool)
+    .await?
+    .ok_or_else(|| blog_not_found(id))?;
+
+    let rows = sqlx::query!(
+        r#"SELECT rev, action AS "action: RevisionAction", blog_user(user_id) AS "user: SqlJson<BlogUser>",
+            created_at, old_values, new_values
+        FROM blog_revisions WHERE blog_id = $1
+        ORDER BY rev DESC
+        LIMIT $2 OFFSET $3"#,
+        id,
+        page.fetch_limit(),
+        params.offset.unwrap_or(0)
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let rows = rows
+        .into_iter()
+        .map(|row| BlogRevision {
+            rev: row.rev,
+            action: row.action,
+            user: row.user,
+            created_at: row.created_at,
+            changes: field_changes(row.old_values.as_ref(), row.new_values.as_ref()),
+        })
+        .collect();
+    let result = page.finish(rows, |_| vec![]);
+    let headers = result.headers(&uri, page.limit, total);
+
+    Ok((StatusCode::OK, headers, Json(result.items)))
+}
+
+/// Revert a blog to a revision
+///
+/// Sets the title, author, url and tags of a blog back to what they were right after a revision,
+/// which is recorded as a new revision. Returns the updated blog. Only the user who submitted the
+/// blog or an admin may revert it, sending its `ETag` in `If-Match` makes sure it did not change
+/// meanwhile
+#[utoipa::path(
+    post,
+    path = "/blogs/{id}/revisions/{rev}/revert",
+    params(
+        ("id" = i32, Path, description = "Blog id"),
+        ("rev" = i32, Path, description = "Number of the revision to go back to")
+    ),
+    security(("bearer" = [])),
+    responses(
+        (status = 500, description = "Internal server error", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 404, description = "Blog or revision not found", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 400, description = "Invalid blog id or revision number", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 401, description = "Missing, expired or invalid token", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 403, description = "Blog was submitted by another user", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 409, description = "Another blog has the url of the revision", body=ProblemDetails, content_type="application/problem+json",
+            headers(("Location" = String, description = "Path of the other blog"))
+        ),
+        (status = 412, description = "Blog changed since the `If-Match` tag", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 422, description = "Revision deleted the blog", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 428, description = "`If-Match` is required but missing", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 200, description = "Blog reverted successfully", body=Blog,
+            headers(
+                ("ETag" = String, description = "Tag of this version of the blog"),
+                ("Last-Modified" = String, description = "When the blog was last edited")
+            )
+        )
+    )
+)]
+pub async fn revert_revision(
+    Extension(pool): Extension<PgPool>,
+    user: AuthUser,
+    BlogOwner(id): BlogOwner,
+    AppPath(RevisionPath { rev }): AppPath<RevisionPath>,
+    if_match: IfMatch,
+) -> Result<impl IntoResponse, AppError> {
+    let values = sqlx::query_scalar!(
+        "SELECT new_values FROM blog_revisions WHERE blog_id = $1 AND rev = $2",
+        id,
+        rev
+    )
+    .fetch_optional(&pool)
+    .await?
+    .ok_or_else(|| {
+        AppError::NotFound(format!("Revision {rev} of the blog with id={id} not found"))
+    })?
+    .ok_or_else(|| {
+        AppError::Unprocessable(format!(
+            "Revision {rev} deleted the blog with id={id}, there is nothing to revert to"
+        ))
+    })?;
+    let body: BlogReplacePayload = deserialize_json_value(values)?;
+    body.validate()?;
+
+    let mut tx = pool.begin().await?;
+    check_if_match(&mut tx, id, &if_match).await?;
+    let blog = replace_blog(&mut tx, id, &body, user.id, RevisionAction::Revert)
+        .await?
+        .ok_or_else(|| blog_not_found(id))?;
+    tx.commit().await?;
+
+    Ok((StatusCode::OK, blog_headers(&blog), Json(blog)))
+}
+
+/// The revisioned fields of a blog outside the trash, its row stays locked until the
+/// transaction ends so nobody changes it before the revision is recorded
+pub async fn lock_blog_values(
+    conn: &mut PgConnection,
+    blog_id: i32,
+) -> Result<Option<Value>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"SELECT blog_values(id) AS "values!" FROM blogs
+        WHERE id = $1 AND deleted_at IS NULL FOR UPDATE"#,
+        blog_id
+    )
+    .fetch_optional(conn)
+    .await
+}
+
+/// Records a change of a blog made by a user in the transaction of the change
+///
+/// The new values are read from the blog, `old_values` should come from `lock_blog_values`
+/// before the change. Updates that changed nothing are not recorded
+pub async fn record_revision(
+    conn: &mut PgConnection,
+    blog_id: i32,
+    user_id: i32,
+    action: RevisionAction,
+    old_values: Option<Value>,
+) -> Result<(), sqlx::Error> {
+    let new_values = match action {
+        RevisionAction::Delete => None,
+        _ => {
+            sqlx::query_scalar!("SELECT blog_values($1)", blog_id)
+                .fetch_one(&mut *conn)
+                .await?
+        }
+    };
+    if matches!(action, RevisionAction::Update | RevisionAction::Revert) && old_values == new_values
+    {
+        return Ok(());
+    }
+
+    sqlx::query!(
+        "INSERT INTO blog_revisions (blog_id, rev, action, user_id, old_values, new_values)
+        SELECT $1, COALESCE(MAX(rev), 0) + 1, $2, $3, $4, $5 FROM blog_revisions WHERE blog_id = $1",
+        blog_id,
+        action as RevisionAction,
+        user_id,
+        old_values,
+        new_values
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// The fields whose value differs between two versions of a blog
+fn field_changes(old: Option<&Value>, new: Option<&Value>) -> Vec<FieldChange> {
+    REVISION_FIELDS
+        .iter()
+        .filter_map(|field| {
+            let old = old.and_then(|values| values.get(field)).cloned();
+            let new = new.and_then(|values| values.get(field)).cloned();
+            (old != new).then(|| FieldChange {
+                field: field.to_string(),
+                old: old.unwrap_or(Value::Null),
+                new: new.unwrap_or(Value::Null),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod revisions_api_test {
+    use crate::test_helper::{setup_test_context, setup_test_context_with, test_settings};
+    use axum::http::StatusCode;
+    use rstest::*;
+    use serde_json::{json, Value};
+
+    // every change is recorded with the fields it touched, unchanged updates are not
+    #[rstest]
+    #[tokio::test]
+    async fn revisions_of_blog() {
+        let mut ctx = setup_test_context().await;
+        ctx.login_as("alice").await;
+        let blog = json!({"title": "t", "author": "a", "url": "http://a.com", "tags": ["rust"]});
+        ctx.server.post("/blogs").json(&blog).await;
+        ctx.server
+            .put("/blogs/1")
+            .json(&json!({"title": "new", "author": "a", "url": "http://b.com", "tags": ["rust"]}))
+            .await
+            .assert_status(StatusCode::OK);
+        ctx.server
+            .patch("/blogs/1")
+            .json(&json!({"title": "new"}))
+            .await
+            .assert_status(StatusCode::OK);
+        ctx.server
+            .patch("/blogs/1")
+            .json(&json!({"tags": []}))
+            .await
+            .assert_status(StatusCode::OK);
+        ctx.server.delete("/blogs/1").await;
+        ctx.server
+            .get("/blogs/1/revisions")
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+        ctx.server.post("/blogs/1/restore").await;
+
+        let response = ctx.server.get("/blogs/1/revisions").await;
+        response.assert_status(StatusCode::OK);
+        response.assert_header("x-total-count", "5");
+        let revisions: Vec<Value> = response.json();
+        let summary: Vec<Value> = revisions
+            .iter()
+            .map(|revision| {
+                json!([
+                    revision["rev"],
+                    revision["action"],
+                    revision["user"]["username"]
+                ])
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                json!([5, "restore", "alice"]),
+                json!([4, "delete", "alice"]),
+                json!([3, "update", "alice"]),
+                json!([2, "update", "alice"]),
+                json!([1, "create", "alice"]),
+            ],
+            summary
+        );
+        assert_eq!(
+            json!([
+                {"field": "tags", "old": ["rust"], "new": []},
+            ]),
+            revisions[2]["changes"]
+        );
+        assert_eq!(
+            json!([
+                {"field": "title", "old": "t", "new": "new"},
+                {"field": "url", "old": "http://a.com", "new": "http://b.com"},
+            ]),
+            revisions[3]["changes"]
+        );
+        assert_eq!(
+            json!({"field": "author", "old": null, "new": "a"}),
+            revisions[4]["changes"][1]
+        );
+        assert_eq!(
+            json!({"field": "author", "old": "a", "new": null}),
+            revisions[1]["changes"][1]
+        );
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // reverting sets the fields back and is a revision itself
+    #[rstest]
+    #[tokio::test]
+    async fn revert_to_revision() {
+        let mut ctx = setup_test_context().await;
+        ctx.login_as("alice").await;
+        let blog = json!({"title": "t", "author": "a", "url": "http://a.com", "tags": ["rust"]});
+        ctx.server.post("/blogs").json(&blog).await;
+        ctx.server
+            .put("/blogs/1")
+            .json(&json!({"title": "new", "author": "b", "url": "http://b.com"}))
+            .await
+            .assert_status(StatusCode::OK);
+
+        ctx.login_as("bob").await;
+        ctx.server
+            .post("/blogs/1/revisions/1/revert")
+            .await
+            .assert_status(StatusCode::FORBIDDEN);
+
+        ctx.login_as_admin("carol").await;
+        let response = ctx.server.post("/blogs/1/revisions/1/revert").await;
+        response.assert_status(StatusCode::OK);
+        let reverted: Value = response.json();
+        assert_eq!(
+            json!(["t", "a", "http://a.com", ["rust"]]),
+            json!([
+                reverted["title"],
+                reverted["author"],
+                reverted["url"],
+                reverted["tags"]
+            ])
+        );
+        let revisions: Vec<Value> = ctx.server.get("/blogs/1/revisions").await.json();
+        assert_eq!(3, revisions[0]["rev"]);
+        assert_eq!("revert", revisions[0]["action"]);
+        assert_eq!("carol", revisions[0]["user"]["username"]);
+
+        // reverting to the current state changes nothing
+        ctx.server
+            .post("/blogs/1/revisions/3/revert")
+            .await
+            .assert_status(StatusCode::OK);
+        ctx.server
+            .get("/blogs/1/revisions")
+            .await
+            .assert_header("x-total-count", "3");
+
+        ctx.server
+            .post("/blogs/1/revisions/9/revert")
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+        ctx.server
+            .post("/blogs/1/revisions/x/revert")
+            .await
+            .assert_status(StatusCode::BAD_REQUEST);
+        ctx.server.delete("/blogs/1").await;
+        ctx.server.post("/blogs/1/restore").await;
+        ctx.server
+            .post("/blogs/1/revisions/4/revert")
+            .await
+            .assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // reverting with a stale If-Match fails with 412 and leaves the blog as it was
+    #[rstest]
+    #[tokio::test]
+    async fn revert_if_match() {
+        let mut ctx = setup_test_context().await;
+        ctx.login_as("alice").await;
+        ctx.server
+            .post("/blogs")
+            .json(&json!({"title": "t", "author": "a", "url": "http://a.com"}))
+            .await;
+        let etag = ctx.server.get("/blogs/1").await.header("etag");
+        let response = ctx
+            .server
+            .put("/blogs/1")
+            .add_header("if-match", etag.clone())
+            .json(&json!({"title": "new", "author": "b", "url": "http://b.com"}))
+            .await;
+        let new_etag = response.header("etag");
+
+        ctx.server
+            .post("/blogs/1/revisions/1/revert")
+            .add_header("if-match", etag)
+            .await
+            .assert_status(StatusCode::PRECONDITION_FAILED);
+        assert_eq!(
+            "new",
+            ctx.server.get("/blogs/1").await.json::<Value>()["title"]
+        );
+
+        let response = ctx
+            .server
+            .post("/blogs/1/revisions/1/revert")
+            .add_header("if-match", new_etag.clone())
+            .await;
+        response.assert_status(StatusCode::OK);
+        assert_eq!("t", response.json::<Value>()["title"]);
+        assert_ne!(new_etag, response.header("etag"));
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // If-Match can be made mandatory for reverting too
+    #[rstest]
+    #[tokio::test]
+    async fn revert_if_match_required() {
+        let mut settings = test_settings();
+        settings.features.require_if_match = true;
+        let mut ctx = setup_test_context_with(settings).await;
+        ctx.login_as("alice").await;
+        ctx.server
+            .post("/blogs")
+            .json(&json!({"title": "t", "author": "a", "url": "http://a.com"}))
+            .await;
+
+        ctx.server
+            .post("/blogs/1/revisions/1/revert")
+            .await
+            .assert_status(StatusCode::PRECONDITION_REQUIRED);
+        let etag = ctx.server.get("/blogs/1").await.header("etag");
+        ctx.server
+            .post("/blogs/1/revisions/1/revert")
+            .add_header("if-match", etag)
+            .await
+            .assert_status(StatusCode::OK);
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+}
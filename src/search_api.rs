@@ -0,0 +1,319 @@
+use axum::{
+    http::{StatusCode, Uri},
+    response::IntoResponse,
+    Extension, Json,
+};
+use serde::Deserialize;
+use sqlx::{types::Json as SqlJson, PgPool};
+use utoipa::IntoParams;
+
+use crate::{
+    errors::{AppError, ProblemDetails},
+    extractors::AppQuery,
+    models::{Blog, BlogUser, FacetCount, SearchFacets, SearchHighlight, SearchHit, SearchResults},
+    pagination::PageParams,
+};
+
+/// Characters with a meaning in the tsquery syntax, they never end up in a lexeme
+const TSQUERY_SPECIAL_CHARS: &[char] = &['&', '|', '!', '(', ')', ':', '*', '<', '>', '\'', '\\'];
+
+#[derive(Deserialize, IntoParams, Debug)]
+#[into_params(parameter_in = Query)]
+pub struct SearchParams {
+    /// Words to look for in title, author and url. `"quoted words"` must appear next to
+    /// each other, a trailing `*` matches any word starting with the prefix
+    #[param(example = "\"clean code\" archit*")]
+    pub q: String,
+}
+
+/// Search blogs
+///
+/// Full-text search over title, author and url. Results are ranked by relevance and come with
+/// the matching words highlighted, facets count the matches per author and per domain
+#[utoipa::path(
+    get,
+    path = "/blogs/search",
+    params(SearchParams, PageParams),
+    responses(
+        (status = 500, description = "Internal server error", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 400, description = "Missing or empty query, invalid paging parameters", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 200, description = "Search results", body=SearchResults,
+            headers(
+                ("X-Total-Count" = i64, description = "Total number of matching blogs"),
+                ("Link" = String, description = "Links to the `prev` and `next` pages")
+            )
+        )
+    )
+)]
+pub async fn search_blogs(
+    Extension(pool): Extension<PgPool>,
+    uri: Uri,
+    AppQuery(params): AppQuery<SearchParams>,
+    AppQuery(page_params): AppQuery<PageParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let page = page_params.offset_page()?;
+    let query = to_tsquery_text(&params.q).ok_or_else(|| {
+        AppError::invalid_field(
+            "Invalid query parameter",
+            "q",
+            "must contain at least one word".to_string(),
+        )
+    })?;
+    let offset = page_params.offset.unwrap_or(0);
+
+    let rows = sqlx::query!(
+        r#"SELECT id, title, author, url, blog_tag_names(id) AS "tags!", likes, comment_count, created_at, updated_at, version,
+            blog_user(user_id) AS "user: SqlJson<BlogUser>",
+            ts_rank(search, query) AS "rank!",
+            ts_headline('english', escaped.escaped_title, query, 'HighlightAll=true, StartSel=<mark>, StopSel=</mark>') AS "title_highlight!",
+            ts_headline('english', escaped.escaped_author, query, 'HighlightAll=true, StartSel=<mark>, StopSel=</mark>') AS "author_highlight!"
+        FROM blogs, to_tsquery('english', $1) query,
+            -- highlights are HTML, the stored text must not bring markup of its own
+            LATERAL (SELECT
+                replace(replace(replace(title, '&', '&amp;'), '<', '&lt;'), '>', '&gt;') AS escaped_title,
+                replace(replace(replace(author, '&', '&amp;'), '<', '&lt;'), '>', '&gt;') AS escaped_author
+            ) escaped
+        WHERE search @@ query AND deleted_at IS NULL
+        ORDER BY ts_rank(search, query) DESC, id
+        LIMIT $2 OFFSET $3"#,
+        query,
+        page.fetch_limit(),
+        offset
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let authors = sqlx::query_as!(
+        FacetCount,
+        r#"SELECT author AS "value!", COUNT(*) AS "count!"
+        FROM blogs, to_tsquery('english', $1) query
+        WHERE search @@ query AND deleted_at IS NULL
+        GROUP BY author
+        ORDER BY COUNT(*) DESC, author"#,
+        query
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let domains = sqlx::query_as!(
+        FacetCount,
+        r#"SELECT domain AS "value!", COUNT(*) AS "count!"
+        FROM blogs, to_tsquery('english', $1) query
+        WHERE search @@ query AND deleted_at IS NULL AND domain IS NOT NULL
+        GROUP BY domain
+        ORDER BY COUNT(*) DESC, domain"#,
+        query
+    )
+    .fetch_all(&pool)
+    .await?;
+
+    let hits = rows
+        .into_iter()
+        .map(|row| SearchHit {
+            blog: Blog {
+                id: row.id,
+                title: row.title,
+                author: row.author,
+                url: row.url,
+                tags: row.tags,
+                likes: row.likes,
+                comment_count: row.comment_count,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+                version: row.version,
+                user: row.user,
+            },
+            rank: row.rank,
+            highlight: SearchHighlight {
+                title: row.title_highlight,
+                author: row.author_highlight,
+            },
+        })
+        .collect();
+    let total = authors.iter().map(|facet| facet.count).sum();
+    let result = page.finish(hits, |_| vec![]);
+    let headers = result.headers(&uri, page.limit, total);
+
+    Ok((
+        StatusCode::OK,
+        headers,
+        Json(SearchResults {
+            total,
+            results: result.items,
+            facets: SearchFacets { authors, domains },
+        }),
+    ))
+}
+
+/// Turns the user's query into tsquery syntax, every term must match
+///
+/// `"a b"` becomes the phrase `a <-> b` and `pre*` the prefix `pre:*`. Returns `None` when
+/// there is nothing to search for
+fn to_tsquery_text(q: &str) -> Option<String> {
+    let mut terms = vec![];
+    for (i, part) in q.split('"').enumerate() {
+        let words: Vec<String> = part.split_whitespace().filter_map(to_lexeme).collect();
+        // odd parts are between quotes
+        if i % 2 == 1 && words.len() > 1 {
+            terms.push(format!("({})", words.join(" <-> ")));
+        } else {
+            terms.extend(words);
+        }
+    }
+
+    (!terms.is_empty()).then(|| terms.join(" & "))
+}
+
+fn to_lexeme(word: &str) -> Option<String> {
+    let prefix = word.ends_with('*');
+    let lexeme: String = word
+        .chars()
+        .filter(|c| !TSQUERY_SPECIAL_CHARS.contains(c))
+        .collect();
+    if lexeme.is_empty() {
+        return None;
+    }
+
+    Some(if prefix {
+        format!("'{lexeme}':*")
+    } else {
+        format!("'{lexeme}'")
+    })
+}
+
+#[cfg(test)]
+mod search_api_test {
+    use crate::test_helper::{insert_test_values, setup_test_context};
+    use axum::http::StatusCode;
+    use rstest::*;
+    use serde_json::{json, Value};
+
+    // blogs matching the query are returned, most relevant first
+    #[rstest]
+    #[case::stemmed_word("/blogs/search?q=harmful", vec![2, 4])]
+    #[case::author("/blogs/search?q=dijkstra", vec![2, 3])]
+    #[case::prefix("/blogs/search?q=arch*", vec![4])]
+    #[case::phrase("/blogs/search?q=%22type%20wars%22", vec![5])]
+    #[case::phrase_wrong_order("/blogs/search?q=%22wars%20type%22", vec![])]
+    #[case::domain("/blogs/search?q=reactpatterns.com", vec![1])]
+    #[tokio::test]
+    async fn search_blogs(#[case] endpoint: &str, #[case] expected_ids: Vec<i64>) {
+        let ctx = setup_test_context().await;
+        insert_test_values(&ctx.pool)
+            .await
+            .expect("Expected insert statement to work");
+
+        let response = ctx.server.get(endpoint).await;
+
+        response.assert_status(StatusCode::OK);
+        let body: Value = response.json();
+        let ids: Vec<i64> = body["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|hit| hit["id"].as_i64().unwrap())
+            .collect();
+        assert_eq!(expected_ids, ids);
+        assert_eq!(expected_ids.len() as i64, body["total"]);
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // matching words are highlighted and counted per author and domain
+    #[rstest]
+    #[tokio::test]
+    async fn search_blogs_highlights_and_facets() {
+        let ctx = setup_test_context().await;
+        insert_test_values(&ctx.pool)
+            .await
+            .expect("Expected insert statement to work");
+
+        let response = ctx.server.get("/blogs/search?q=harm").await;
+
+        response.assert_status(StatusCode::OK);
+        let body: Value = response.json();
+        assert_eq!(
+            json!([2, 4]),
+            json!(body["results"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|hit| hit["id"].clone())
+                .collect::<Vec<_>>())
+        );
+        assert_eq!(
+            "Go To Statement Considered <mark>Harmful</mark>",
+            body["results"][0]["highlight"]["title"]
+        );
+        assert_eq!(
+            json!({
+                "authors": [
+                    {"value": "Edsger W. Dijkstra", "count": 1},
+                    {"value": "Robert C. Martin", "count": 1},
+                ],
+                "domains": [
+                    {"value": "blog.cleancoder.com", "count": 1},
+                    {"value": "cs.utexas.edu", "count": 1},
+                ],
+            }),
+            body["facets"]
+        );
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // markup stored in a blog is escaped in the highlights, only the marks are tags
+    #[rstest]
+    #[tokio::test]
+    async fn search_blogs_escapes_highlights() {
+        let mut ctx = setup_test_context().await;
+        ctx.login_as("root").await;
+        ctx.server
+            .post("/blogs")
+            .json(&json!({
+                "title": "<script>alert(1)</script> Rust & you",
+                "author": "<img src=x onerror=alert(1)> Rustacean",
+                "url": "http://rust.com",
+            }))
+            .await
+            .assert_status(StatusCode::CREATED);
+
+        let response = ctx.server.get("/blogs/search?q=rust").await;
+
+        response.assert_status(StatusCode::OK);
+        let body: Value = response.json();
+        assert_eq!(
+            json!({
+                "title": "&lt;script&gt;alert(1)&lt;/script&gt; <mark>Rust</mark> &amp; you",
+                "author": "&lt;img src=x onerror=alert(1)&gt; Rustacean",
+            }),
+            body["results"][0]["highlight"]
+        );
+        assert_eq!(
+            "<script>alert(1)</script> Rust & you",
+            body["results"][0]["title"]
+        );
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // a query without words is rejected
+    #[rstest]
+    #[case::missing("/blogs/search")]
+    #[case::only_operators("/blogs/search?q=%26%7C!")]
+    #[tokio::test]
+    async fn search_blogs_invalid_query(#[case] endpoint: &str) {
+        let ctx = setup_test_context().await;
+
+        let response = ctx.server.get(endpoint).await;
+
+        response.assert_status(StatusCode::BAD_REQUEST);
+        assert!(response.json::<Value>()["errors"].get("q").is_some());
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+}
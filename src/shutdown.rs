@@ -0,0 +1,306 @@
+use std::{future::IntoFuture, io, sync::Arc, time::Duration as StdDuration};
+
+use axum::Router;
+use tokio::{net::TcpListener, sync::watch};
+use tracing::{info, warn};
+
+/// Whether the server is shutting down, shared by the listener, the readiness check and the
+/// background tasks
+///
+/// Clones share the same state, once triggered it stays triggered
+#[derive(Clone)]
+pub struct Shutdown {
+    sender: Arc<watch::Sender<bool>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self {
+            sender: Arc::new(watch::Sender::new(false)),
+        }
+    }
+}
+
+impl Shutdown {
+    /// Starts the shutdown, later calls do nothing
+    pub fn trigger(&self) {
+        self.sender.send_replace(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.sender.borrow()
+    }
+
+    /// Resolves once the shutdown has started, right away when it already has
+    pub async fn triggered(&self) {
+        let mut receiver = self.sender.subscribe();
+        // the sender lives as long as self, the channel cannot close while waiting
+        let _ = receiver.wait_for(|triggered| *triggered).await;
+    }
+}
+
+/// Triggers `shutdown` on SIGTERM or SIGINT (Ctrl+C)
+pub async fn trigger_on_signal(shutdown: Shutdown) {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!("Failed to listen for SIGINT: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                warn!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+        // triggered some other way, nothing left to do
+        _ = shutdown.triggered() => return,
+    }
+    info!("Shutting down, finishing the requests in flight");
+    shutdown.trigger();
+}
+
+/// Serves `app` until `shutdown` is triggered and `delay` has passed
+///
+/// During `delay` connections are still accepted, so the readiness probe can answer 503 and load
+/// balancers stop sending traffic before connections are refused. After it no connection is
+/// accepted, the requests in flight get up to `drain_timeout` to finish and idle connections are
+/// closed. Requests still running after that are abandoned
+pub async fn serve(
+    listener: TcpListener,
+    app: Router,
+    shutdown: Shutdown,
+    delay: StdDuration,
+    drain_timeout: StdDuration,
+) -> io::Result<()> {
+    let stop_accepting = {
+        let shutdown = shutdown.clone();
+        async move {
+            shutdown.triggered().await;
+            if !delay.is_zero() {
+                info!(
+                    "Accepting connections for {} more seconds",
+                    delay.as_secs_f64()
+                );
+                tokio::time::sleep(delay).await;
+            }
+        }
+    };
+    let server = axum::serve(listener, app.into_make_service())
+        .with_graceful_shutdown(stop_accepting)
+        .into_future();
+    let deadline = async {
+        shutdown.triggered().await;
+        tokio::time::sleep(delay + drain_timeout).await;
+    };
+
+    tokio::select! {
+        result = server => result,
+        _ = deadline => {
+            warn!(
+                "Requests still in flight after {} seconds, abandoning them",
+                drain_timeout.as_secs_f64()
+            );
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod shutdown_test {
+    use super::{serve, Shutdown};
+    use crate::{
+        app,
+        test_helper::{insert_test_values, setup_test_context, test_settings},
+    };
+    use rstest::*;
+    use serde_json::Value;
+    use sqlx::{PgPool, Postgres, Transaction};
+    use std::{
+        net::SocketAddr,
+        time::{Duration, Instant},
+    };
+    use tokio::{
+        net::{TcpListener, TcpStream},
+        task::JoinHandle,
+    };
+
+    /// Serves the app on a free port, like `main` does
+    async fn start_server(
+        pool: &PgPool,
+        shutdown: &Shutdown,
+        delay: Duration,
+        drain_timeout: Duration,
+    ) -> (SocketAddr, JoinHandle<std::io::Result<()>>) {
+        let app = app(pool.clone(), &test_settings(), shutdown.clone()).await;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(serve(listener, app, shutdown.clone(), delay, drain_timeout));
+        (addr, server)
+    }
+
+    /// Sends `GET path` to the server on a new connection in the background, resolving to the
+    /// status and the body
+    fn get(addr: SocketAddr, path: &str) -> JoinHandle<(u16, Value)> {
+        let client = httpc_test::new_client(format!("http://{addr}")).unwrap();
+        // the requests of the client itself cannot move to another task
+        let request = client.reqwest_client().get(format!("http://{addr}{path}"));
+        tokio::spawn(async move {
+            let response = request.send().await.unwrap();
+            let status = response.status().as_u16();
+            (
+                status,
+                serde_json::from_str(&response.text().await.unwrap()).unwrap(),
+            )
+        })
+    }
+
+    /// Locks the blogs until the transaction ends, requests reading them wait meanwhile
+    async fn lock_blogs(pool: &PgPool) -> Transaction<'static, Postgres> {
+        let mut tx = pool.begin().await.unwrap();
+        sqlx::query("LOCK TABLE blogs IN ACCESS EXCLUSIVE MODE")
+            .execute(&mut *tx)
+            .await
+            .unwrap();
+        tx
+    }
+
+    /// Waits until a query of the server is blocked by the lock
+    async fn wait_for_blocked_query(pool: &PgPool) {
+        loop {
+            let waiting: i64 =
+                sqlx::query_scalar("SELECT COUNT(*) FROM pg_locks WHERE NOT granted")
+                    .fetch_one(pool)
+                    .await
+                    .unwrap();
+            if waiting > 0 {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    // a request that started before the shutdown completes, new connections are refused
+    #[rstest]
+    #[tokio::test]
+    async fn in_flight_request_completes() {
+        let ctx = setup_test_context().await;
+        insert_test_values(&ctx.pool)
+            .await
+            .expect("Expected insert statement to work");
+        let shutdown = Shutdown::default();
+        let (addr, server) = start_server(
+            &ctx.pool,
+            &shutdown,
+            Duration::ZERO,
+            Duration::from_secs(30),
+        )
+        .await;
+
+        let tx = lock_blogs(&ctx.pool).await;
+        let request = get(addr, "/blogs");
+        wait_for_blocked_query(&ctx.pool).await;
+
+        shutdown.trigger();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while TcpStream::connect(addr).await.is_ok() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Expected the server to stop accepting connections");
+        assert!(!request.is_finished());
+        assert!(!server.is_finished());
+
+        tx.commit().await.unwrap();
+        let (status, blogs) = request.await.unwrap();
+        assert_eq!(200, status);
+        assert_eq!(6, blogs.as_array().map(Vec::len).unwrap_or_default());
+        tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .expect("Expected the server to stop once the request completed")
+            .unwrap()
+            .unwrap();
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // requests still running after the drain timeout do not keep the server up
+    #[rstest]
+    #[tokio::test]
+    async fn drain_timeout() {
+        let ctx = setup_test_context().await;
+        let shutdown = Shutdown::default();
+        let (addr, server) = start_server(
+            &ctx.pool,
+            &shutdown,
+            Duration::ZERO,
+            Duration::from_millis(200),
+        )
+        .await;
+
+        let tx = lock_blogs(&ctx.pool).await;
+        let request = get(addr, "/blogs");
+        wait_for_blocked_query(&ctx.pool).await;
+
+        shutdown.trigger();
+        tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .expect("Expected the server to stop after the drain timeout")
+            .unwrap()
+            .unwrap();
+        assert!(!request.is_finished());
+
+        // cleanup
+        request.abort();
+        tx.rollback().await.unwrap();
+        ctx.cleanup().await;
+    }
+
+    // during the delay connections are still accepted and /readyz tells the shutdown started,
+    // only then are connections refused
+    #[rstest]
+    #[tokio::test]
+    async fn readyz_during_shutdown_delay() {
+        let ctx = setup_test_context().await;
+        let shutdown = Shutdown::default();
+        let delay = Duration::from_secs(1);
+        let (addr, server) =
+            start_server(&ctx.pool, &shutdown, delay, Duration::from_secs(30)).await;
+        assert_eq!(200, get(addr, "/readyz").await.unwrap().0);
+
+        let started = Instant::now();
+        shutdown.trigger();
+        let (status, body) = get(addr, "/readyz").await.unwrap();
+        assert_eq!(503, status);
+        assert_eq!(
+            "the server is shutting down",
+            body["checks"]["shutdown"]["detail"]
+        );
+        assert!(!server.is_finished());
+
+        tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .expect("Expected the server to stop after the delay")
+            .unwrap()
+            .unwrap();
+        assert!(started.elapsed() >= delay);
+        assert!(TcpStream::connect(addr).await.is_err());
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+}
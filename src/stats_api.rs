@@ -0,0 +1,189 @@
+use axum::{http::StatusCode, response::IntoResponse, Extension, Json};
+use sqlx::PgPool;
+
+use crate::{
+    blogs_api::fetch_blog,
+    errors::{AppError, ProblemDetails},
+    models::{AuthorStats, AuthorsStats, BlogStats},
+};
+
+/// Get blog statistics
+///
+/// Returns the number of blogs, the sum of their likes and the blog with the most likes.
+/// Between blogs with as many likes the one added first wins
+#[utoipa::path(
+    get,
+    path = "/stats",
+    responses(
+        (status = 500, description = "Internal server error", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 200, description = "Statistics computed successfully", body=BlogStats)
+    )
+)]
+pub async fn get_stats(Extension(pool): Extension<PgPool>) -> Result<impl IntoResponse, AppError> {
+    let totals = sqlx::query!(
+        r#"SELECT COUNT(*) AS "blogs!", COALESCE(SUM(likes), 0) AS "total_likes!"
+        FROM blogs WHERE deleted_at IS NULL"#
+    )
+    .fetch_one(&pool)
+    .await?;
+    let favorite_id = sqlx::query_scalar!(
+        "SELECT id FROM blogs WHERE deleted_at IS NULL ORDER BY likes DESC, id LIMIT 1"
+    )
+    .fetch_optional(&pool)
+    .await?;
+    let favorite_blog = match favorite_id {
+        Some(id) => fetch_blog(&pool, id).await?,
+        None => None,
+    };
+
+    Ok((
+        StatusCode::OK,
+        Json(BlogStats {
+            blogs: totals.blogs,
+            total_likes: totals.total_likes,
+            favorite_blog,
+        }),
+    ))
+}
+
+/// Get author statistics
+///
+/// Returns the number of authors, the author with the most blogs and the author with the most
+/// likes over all their blogs. Ties go to the author whose first blog was added first
+#[utoipa::path(
+    get,
+    path = "/stats/authors",
+    responses(
+        (status = 500, description = "Internal server error", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 200, description = "Statistics computed successfully", body=AuthorsStats)
+    )
+)]
+pub async fn get_author_stats(
+    Extension(pool): Extension<PgPool>,
+) -> Result<impl IntoResponse, AppError> {
+    let authors = sqlx::query_scalar!(
+        r#"SELECT COUNT(DISTINCT author) AS "count!" FROM blogs WHERE deleted_at IS NULL"#
+    )
+    .fetch_one(&pool)
+    .await?;
+    let most_blogs = sqlx::query_as!(
+        AuthorStats,
+        r#"SELECT author, COUNT(*) AS "blogs!", COALESCE(SUM(likes), 0) AS "likes!"
+        FROM blogs WHERE deleted_at IS NULL GROUP BY author
+        ORDER BY COUNT(*) DESC, MIN(id)
+        LIMIT 1"#
+    )
+    .fetch_optional(&pool)
+    .await?;
+    let most_likes = sqlx::query_as!(
+        AuthorStats,
+        r#"SELECT author, COUNT(*) AS "blogs!", COALESCE(SUM(likes), 0) AS "likes!"
+        FROM blogs WHERE deleted_at IS NULL GROUP BY author
+        ORDER BY SUM(likes) DESC, MIN(id)
+        LIMIT 1"#
+    )
+    .fetch_optional(&pool)
+    .await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(AuthorsStats {
+            authors,
+            most_blogs,
+            most_likes,
+        }),
+    ))
+}
+
+#[cfg(test)]
+mod stats_api_test {
+    use crate::test_helper::{get_test_blogs, insert_test_values, setup_test_context};
+    use axum::http::StatusCode;
+    use rstest::*;
+    use serde_json::{json, Value};
+
+    // without blogs the totals are zero and nothing stands out
+    #[rstest]
+    #[tokio::test]
+    async fn stats_without_blogs() {
+        let ctx = setup_test_context().await;
+
+        let response = ctx.server.get("/stats").await;
+        response.assert_status(StatusCode::OK);
+        response.assert_json(&json!({"blogs": 0, "total_likes": 0, "favorite_blog": null}));
+
+        let response = ctx.server.get("/stats/authors").await;
+        response.assert_status(StatusCode::OK);
+        response.assert_json(&json!({"authors": 0, "most_blogs": null, "most_likes": null}));
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // the course's list helpers, computed over the test blogs
+    #[rstest]
+    #[tokio::test]
+    async fn stats_of_test_blogs() {
+        let ctx = setup_test_context().await;
+        insert_test_values(&ctx.pool)
+            .await
+            .expect("Expected insert statement to work");
+
+        let response = ctx.server.get("/stats").await;
+        response.assert_json(&json!({
+            "blogs": 6,
+            "total_likes": 36,
+            "favorite_blog": get_test_blogs()[2],
+        }));
+
+        let response = ctx.server.get("/stats/authors").await;
+        response.assert_json(&json!({
+            "authors": 3,
+            "most_blogs": {"author": "Robert C. Martin", "blogs": 3, "likes": 12},
+            "most_likes": {"author": "Edsger W. Dijkstra", "blogs": 2, "likes": 17},
+        }));
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // ties go to what was added first
+    #[rstest]
+    #[tokio::test]
+    async fn stats_ties() {
+        let ctx = setup_test_context().await;
+        insert_test_values(&ctx.pool)
+            .await
+            .expect("Expected insert statement to work");
+        // Michael Chan ties with Robert C. Martin on blogs and with Edsger W. Dijkstra on
+        // likes, their first blog was added first
+        sqlx::query(
+            "INSERT INTO blogs (title, author, url, likes) VALUES
+                ('a', 'Michael Chan', 'http://a.com', 12),
+                ('b', 'Michael Chan', 'http://b.com', 0)",
+        )
+        .execute(&ctx.pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "UPDATE blogs SET likes = CASE id WHEN 2 THEN 7 ELSE 12 END WHERE id IN (2, 4)",
+        )
+        .execute(&ctx.pool)
+        .await
+        .unwrap();
+
+        // blogs 3, 4 and 7 have 12 likes
+        let stats: Value = ctx.server.get("/stats").await.json();
+        assert_eq!(3, stats["favorite_blog"]["id"]);
+
+        let response = ctx.server.get("/stats/authors").await;
+        response.assert_json(&json!({
+            "authors": 3,
+            "most_blogs": {"author": "Michael Chan", "blogs": 3, "likes": 19},
+            "most_likes": {"author": "Michael Chan", "blogs": 3, "likes": 19},
+        }));
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+}
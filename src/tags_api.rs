@@ -0,0 +1,516 @@
+use axum::{
+    http::{StatusCode, Uri},
+    response::IntoResponse,
+    Extension, Json,
+};
+use serde_json::Value;
+use sqlx::{PgConnection, PgPool};
+
+use crate::{
+    auth::{AdminUser, AuthUser},
+    errors::{AppError, ProblemDetails},
+    extractors::{AppPath, AppQuery, ValidatedJson},
+    models::{
+        normalize_tag, normalize_tags, RevisionAction, Tag, TagMergePayload, TagRenamePayload,
+    },
+    pagination::PageParams,
+    revisions_api::record_revision,
+};
+
+/// Get all tags
+///
+/// Returns a page of tags with the number of blogs using them, most used first. The total
+/// number of tags is returned in `X-Total-Count`
+#[utoipa::path(
+    get,
+    path = "/tags",
+    params(PageParams),
+    responses(
+        (status = 500, description = "Internal server error", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 400, description = "Invalid paging parameters", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 200, description = "Tags retrieved successfully", body=[Tag],
+            headers(
+                ("X-Total-Count" = i64, description = "Total number of tags"),
+                ("Link" = String, description = "Links to the `prev` and `next` pages")
+            )
+        )
+    )
+)]
+pub async fn get_tags(
+    Extension(pool): Extension<PgPool>,
+    uri: Uri,
+    AppQuery(params): AppQuery<PageParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let page = params.offset_page()?;
+
+    let rows = sqlx::query_as!(
+        Tag,
+        r#"SELECT name, tag_blog_count(id) AS "count!"
+        FROM tags
+        ORDER BY tag_blog_count(id) DESC, name
+        LIMIT $1 OFFSET $2"#,
+        page.fetch_limit(),
+        params.offset.unwrap_or(0)
+    )
+    .fetch_all(&pool)
+    .await?;
+    let total = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM tags"#)
+        .fetch_one(&pool)
+        .await?;
+
+    let result = page.finish(rows, |_| vec![]);
+    let headers = result.headers(&uri, page.limit, total);
+
+    Ok((StatusCode::OK, headers, Json(result.items)))
+}
+
+/// Rename a tag
+///
+/// Renames a tag on every blog using it, which is recorded as a revision of each of them. Fails
+/// when a tag with the new name exists, merge the tags instead. Only admins may rename tags
+#[utoipa::path(
+    patch,
+    path = "/tags/{name}",
+    params(("name" = String, Path, description = "Tag name")),
+    request_body(content = TagRenamePayload, content_type = "application/merge-patch+json"),
+    security(("bearer" = [])),
+    responses(
+        (status = 500, description = "Internal server error", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 404, description = "Tag not found", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 400, description = "Malformed or invalid request body", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 401, description = "Missing, expired or invalid token", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 403, description = "User is not an admin", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 409, description = "A tag with the new name exists", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 415, description = "Request body is not JSON", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 200, description = "Tag renamed successfully", body=Tag)
+    )
+)]
+pub async fn rename_tag(
+    Extension(pool): Extension<PgPool>,
+    user: AuthUser,
+    _admin: AdminUser,
+    AppPath(name): AppPath<String>,
+    ValidatedJson(body): ValidatedJson<TagRenamePayload>,
+) -> Result<impl IntoResponse, AppError> {
+    let name = normalize_tag(&name);
+    let new_name = normalize_tag(&body.name);
+
+    let mut tx = pool.begin().await?;
+    let blogs = lock_tagged_blogs(&mut tx, &name).await?;
+    let tag = sqlx::query_as!(
+        Tag,
+        r#"UPDATE tags SET name = $1 WHERE name = $2
+        RETURNING name, tag_blog_count(tags.id) AS "count!""#,
+        new_name,
+        name
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| match &e {
+        sqlx::Error::Database(db_err) if db_err.constraint() == Some("tags_name_key") => {
+            AppError::Conflict(format!(
+                "Tag `{new_name}` already exists, merge `{name}` into it instead"
+            ))
+        }
+        _ => AppError::from(e),
+    })?
+    .ok_or_else(|| tag_not_found(&name))?;
+    record_tag_revisions(&mut tx, blogs, user.id).await?;
+    tx.commit().await?;
+
+    Ok((StatusCode::OK, Json(tag)))
+}
+
+/// Merge a tag into another
+///
+/// Moves every blog using a tag to another tag, which is created if needed, and deletes the
+/// merged tag. The change is recorded as a revision of each blog. Returns the tag that was
+/// merged into. Only admins may merge tags
+#[utoipa::path(
+    post,
+    path = "/tags/{name}/merge",
+    params(("name" = String, Path, description = "Name of the tag to merge")),
+    request_body = TagMergePayload,
+    security(("bearer" = [])),
+    responses(
+        (status = 500, description = "Internal server error", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 404, description = "Tag not found", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 400, description = "Malformed or invalid request body", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 401, description = "Missing, expired or invalid token", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 403, description = "User is not an admin", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 415, description = "Request body is not JSON", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 422, description = "Tag is merged into itself", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 200, description = "Tags merged successfully", body=Tag)
+    )
+)]
+pub async fn merge_tag(
+    Extension(pool): Extension<PgPool>,
+    user: AuthUser,
+    _admin: AdminUser,
+    AppPath(name): AppPath<String>,
+    ValidatedJson(body): ValidatedJson<TagMergePayload>,
+) -> Result<impl IntoResponse, AppError> {
+    let name = normalize_tag(&name);
+    let into = normalize_tag(&body.into);
+    if name == into {
+        return Err(AppError::Unprocessable(format!(
+            "Tag `{name}` cannot be merged into itself"
+        )));
+    }
+
+    let mut tx = pool.begin().await?;
+    let blogs = lock_tagged_blogs(&mut tx, &name).await?;
+    let source = sqlx::query_scalar!("SELECT id FROM tags WHERE name = $1 FOR UPDATE", name)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| tag_not_found(&name))?;
+    sqlx::query!(
+        "INSERT INTO tags (name) VALUES ($1) ON CONFLICT (name) DO NOTHING",
+        into
+    )
+    .execute(&mut *tx)
+    .await?;
+    let target = sqlx::query_scalar!("SELECT id FROM tags WHERE name = $1", into)
+        .fetch_one(&mut *tx)
+        .await?;
+
+    // blogs that already have both tags keep a single one
+    sqlx::query!(
+        "INSERT INTO blog_tags (blog_id, tag_id)
+        SELECT blog_id, $2 FROM blog_tags WHERE tag_id = $1
+        ON CONFLICT DO NOTHING",
+        source,
+        target
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!("DELETE FROM tags WHERE id = $1", source)
+        .execute(&mut *tx)
+        .await?;
+    let tag = sqlx::query_as!(
+        Tag,
+        r#"SELECT name, tag_blog_count(tags.id) AS "count!"
+        FROM tags WHERE id = $1"#,
+        target
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    record_tag_revisions(&mut tx, blogs, user.id).await?;
+    tx.commit().await?;
+
+    Ok((StatusCode::OK, Json(tag)))
+}
+
+/// The revisioned fields of the blogs using a tag, trashed ones included, their rows stay
+/// locked until the transaction ends
+async fn lock_tagged_blogs(
+    conn: &mut PgConnection,
+    tag: &str,
+) -> Result<Vec<(i32, Value)>, sqlx::Error> {
+    let blogs = sqlx::query!(
+        r#"SELECT blogs.id, blog_values(blogs.id) AS "values!" FROM blogs
+        JOIN blog_tags ON blog_tags.blog_id = blogs.id
+        JOIN tags ON tags.id = blog_tags.tag_id
+        WHERE tags.name = $1
+        ORDER BY blogs.id FOR UPDATE OF blogs"#,
+        tag
+    )
+    .fetch_all(conn)
+    .await?;
+
+    Ok(blogs
+        .into_iter()
+        .map(|blog| (blog.id, blog.values))
+        .collect())
+}
+
+/// Records the change of the tags of the blogs locked by `lock_tagged_blogs`
+async fn record_tag_revisions(
+    conn: &mut PgConnection,
+    blogs: Vec<(i32, Value)>,
+    user_id: i32,
+) -> Result<(), sqlx::Error> {
+    for (blog_id, old_values) in blogs {
+        record_revision(
+            conn,
+            blog_id,
+            user_id,
+            RevisionAction::Update,
+            Some(old_values),
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Replaces the tags of a blog, creating the tags that do not exist yet
+pub async fn set_blog_tags(
+    conn: &mut PgConnection,
+    blog_id: i32,
+    tags: &[String],
+) -> Result<(), sqlx::Error> {
+    let tags = normalize_tags(tags);
+
+    sqlx::query!(
+        "INSERT INTO tags (name) SELECT unnest($1::text[]) ON CONFLICT (name) DO NOTHING",
+        &tags[..]
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM blog_tags WHERE blog_id = $1
+        AND tag_id NOT IN (SELECT id FROM tags WHERE name = ANY($2))",
+        blog_id,
+        &tags[..]
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query!(
+        "INSERT INTO blog_tags (blog_id, tag_id)
+        SELECT $1, id FROM tags WHERE name = ANY($2)
+        ON CONFLICT DO NOTHING",
+        blog_id,
+        &tags[..]
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+fn tag_not_found(name: &str) -> AppError {
+    AppError::NotFound(format!("Tag `{name}` not found"))
+}
+
+#[cfg(test)]
+mod tags_api_test {
+    use crate::test_helper::setup_test_context;
+    use axum::http::StatusCode;
+    use rstest::*;
+    use serde_json::{json, Value};
+
+    async fn add_blog(server: &axum_test::TestServer, title: &str, tags: Value) -> Value {
+        let response = server
+            .post("/blogs")
+            .json(&json!({"title": title, "author": "a", "url": format!("http://a.com/{title}"), "tags": tags}))
+            .await;
+        response.assert_status(StatusCode::CREATED);
+        response.json()
+    }
+
+    fn titles(response: &axum_test::TestResponse) -> Vec<String> {
+        response
+            .json::<Vec<Value>>()
+            .iter()
+            .map(|blog| blog["title"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    // tags are normalized, replaced by PUT and PATCH and counted by /tags
+    #[rstest]
+    #[tokio::test]
+    async fn blog_tags() {
+        let mut ctx = setup_test_context().await;
+        ctx.login_as("root").await;
+
+        let blog = add_blog(&ctx.server, "a", json!([" Rust", "testing", "RUST"])).await;
+        assert_eq!(json!(["rust", "testing"]), blog["tags"]);
+        add_blog(&ctx.server, "b", json!(["rust", "architecture"])).await;
+        add_blog(&ctx.server, "c", json!([])).await;
+
+        let response = ctx.server.get("/tags").await;
+        response.assert_status(StatusCode::OK);
+        response.assert_json(&json!([
+            {"name": "rust", "count": 2},
+            {"name": "architecture", "count": 1},
+            {"name": "testing", "count": 1},
+        ]));
+        response.assert_header("x-total-count", "3");
+
+        let response = ctx
+            .server
+            .patch("/blogs/1")
+            .bytes(json!({"tags": ["Testing", "tdd"]}).to_string().into())
+            .content_type("application/merge-patch+json")
+            .await;
+        response.assert_status(StatusCode::OK);
+        assert_eq!(json!(["tdd", "testing"]), response.json::<Value>()["tags"]);
+
+        let response = ctx
+            .server
+            .patch("/blogs/1")
+            .bytes(
+                json!([{"op": "add", "path": "/tags/-", "value": "rust"}])
+                    .to_string()
+                    .into(),
+            )
+            .content_type("application/json-patch+json")
+            .await;
+        response.assert_status(StatusCode::OK);
+        assert_eq!(
+            json!(["rust", "tdd", "testing"]),
+            response.json::<Value>()["tags"]
+        );
+
+        // PUT replaces the whole blog, tags included
+        let response = ctx
+            .server
+            .put("/blogs/2")
+            .json(&json!({"title": "b", "author": "a", "url": "http://a.com"}))
+            .await;
+        response.assert_status(StatusCode::OK);
+        assert_eq!(json!([]), response.json::<Value>()["tags"]);
+        assert_eq!(
+            json!(["rust", "tdd", "testing"]),
+            ctx.server.get("/blogs/1").await.json::<Value>()["tags"]
+        );
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // invalid tags are rejected
+    #[rstest]
+    #[case::blank(json!([" "]))]
+    #[case::spaces(json!(["clean code"]))]
+    #[case::too_long(json!(["a".repeat(51)]))]
+    #[case::too_many(json!((0..11).map(|i| format!("t{i}")).collect::<Vec<_>>()))]
+    #[case::not_strings(json!([1]))]
+    #[tokio::test]
+    async fn invalid_tags(#[case] tags: Value) {
+        let mut ctx = setup_test_context().await;
+        ctx.login_as("root").await;
+
+        let response = ctx
+            .server
+            .post("/blogs")
+            .json(&json!({"title": "t", "author": "a", "url": "http://a.com", "tags": tags}))
+            .await;
+
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let problem: Value = response.json();
+        let fields: Vec<&String> = problem["errors"].as_object().unwrap().keys().collect();
+        assert!(!fields.is_empty() && fields.iter().all(|field| field.starts_with("tags")));
+        ctx.server.get("/tags").await.assert_json(&json!([]));
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // blogs can be filtered by any or all of several tags
+    #[rstest]
+    #[case::one_tag("/blogs?tag=rust", vec!["a", "b"])]
+    #[case::normalized("/blogs?tag=%20RUST", vec!["a", "b"])]
+    #[case::any("/blogs?tag=testing&tag=architecture", vec!["a", "b"])]
+    #[case::all("/blogs?tag=rust&tag=testing&tag_match=all", vec!["a"])]
+    #[case::all_none("/blogs?tag=testing&tag=architecture&tag_match=all", vec![])]
+    #[case::unknown("/blogs?tag=go", vec![])]
+    #[tokio::test]
+    async fn filter_by_tags(#[case] uri: &str, #[case] expected: Vec<&str>) {
+        let mut ctx = setup_test_context().await;
+        ctx.login_as("root").await;
+        add_blog(&ctx.server, "a", json!(["rust", "testing"])).await;
+        add_blog(&ctx.server, "b", json!(["rust", "architecture"])).await;
+        add_blog(&ctx.server, "c", json!([])).await;
+
+        let response = ctx.server.get(uri).await;
+
+        response.assert_status(StatusCode::OK);
+        assert_eq!(expected, titles(&response));
+        response.assert_header("x-total-count", expected.len().to_string());
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // admins can fix typos by renaming a tag or merging it into another one
+    #[rstest]
+    #[tokio::test]
+    async fn rename_and_merge_tags() {
+        let mut ctx = setup_test_context().await;
+        ctx.login_as("root").await;
+        add_blog(&ctx.server, "a", json!(["rust", "tsting"])).await;
+        add_blog(&ctx.server, "b", json!(["rsut", "tsting"])).await;
+        add_blog(&ctx.server, "c", json!(["rust", "rsut"])).await;
+
+        ctx.server
+            .patch("/tags/tsting")
+            .json(&json!({"name": "testing"}))
+            .await
+            .assert_status(StatusCode::FORBIDDEN);
+
+        ctx.login_as_admin("admin").await;
+        let response = ctx
+            .server
+            .patch("/tags/tsting")
+            .json(&json!({"name": "Testing"}))
+            .await;
+        response.assert_status(StatusCode::OK);
+        response.assert_json(&json!({"name": "testing", "count": 2}));
+        ctx.server
+            .patch("/tags/rsut")
+            .json(&json!({"name": "rust"}))
+            .await
+            .assert_status(StatusCode::CONFLICT);
+        ctx.server
+            .patch("/tags/go")
+            .json(&json!({"name": "golang"}))
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+
+        let response = ctx
+            .server
+            .post("/tags/rsut/merge")
+            .json(&json!({"into": "rust"}))
+            .await;
+        response.assert_status(StatusCode::OK);
+        response.assert_json(&json!({"name": "rust", "count": 3}));
+        ctx.server
+            .post("/tags/rust/merge")
+            .json(&json!({"into": "RUST"}))
+            .await
+            .assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+
+        ctx.server.get("/tags").await.assert_json(&json!([
+            {"name": "rust", "count": 3},
+            {"name": "testing", "count": 2},
+        ]));
+        assert_eq!(
+            json!(["rust", "testing"]),
+            ctx.server.get("/blogs/2").await.json::<Value>()["tags"]
+        );
+        assert_eq!(
+            json!(["rust"]),
+            ctx.server.get("/blogs/3").await.json::<Value>()["tags"]
+        );
+
+        // both changes are revisions of the blogs they touched
+        let revisions: Vec<Value> = ctx.server.get("/blogs/2/revisions").await.json();
+        let summary: Vec<Value> = revisions
+            .iter()
+            .map(|revision| json!([revision["action"], revision["user"]["username"]]))
+            .collect();
+        assert_eq!(
+            vec![
+                json!(["update", "admin"]),
+                json!(["update", "admin"]),
+                json!(["create", "root"]),
+            ],
+            summary
+        );
+        assert_eq!(
+            json!([
+                {"field": "tags", "old": ["rsut", "testing"], "new": ["rust", "testing"]},
+            ]),
+            revisions[0]["changes"]
+        );
+        ctx.server
+            .get("/blogs/1/revisions")
+            .await
+            .assert_header("x-total-count", "2");
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+}
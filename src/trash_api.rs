@@ -0,0 +1,386 @@
+use std::time::Duration as StdDuration;
+
+use axum::{
+    http::{StatusCode, Uri},
+    response::IntoResponse,
+    Extension, Json,
+};
+use chrono::{Duration, Utc};
+use sqlx::{types::Json as SqlJson, PgPool};
+use tracing::{error, info};
+
+use crate::{
+    auth::{AdminUser, AuthUser, TrashedBlogOwner},
+    blogs_api::{blog_not_found, check_duplicate_url, fetch_blog},
+    config::TrashConfig,
+    errors::{AppError, ProblemDetails},
+    extractors::AppQuery,
+    models::{Blog, BlogUser, PurgeReport, RevisionAction, TrashedBlog},
+    pagination::PageParams,
+    revisions_api::record_revision,
+    shutdown::Shutdown,
+};
+
+/// How often the server purges the blogs that stayed in the trash past the retention
+const PURGE_INTERVAL: StdDuration = StdDuration::from_secs(60 * 60);
+
+/// Get the trash
+///
+/// Returns a page of the deleted blogs of the logged in user, most recently deleted first,
+/// with the time each of them is purged at. Admins see the deleted blogs of every user. The
+/// total number of blogs in the trash is returned in `X-Total-Count`
+#[utoipa::path(
+    get,
+    path = "/trash",
+    params(PageParams),
+    security(("bearer" = [])),
+    responses(
+        (status = 500, description = "Internal server error", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 400, description = "Invalid paging parameters", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 401, description = "Missing, expired or invalid token", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 200, description = "Trash retrieved successfully", body=[TrashedBlog],
+            headers(
+                ("X-Total-Count" = i64, description = "Total number of blogs in the trash"),
+                ("Link" = String, description = "Links to the `prev` and `next` pages")
+            )
+        )
+    )
+)]
+pub async fn get_trash(
+    Extension(pool): Extension<PgPool>,
+    Extension(config): Extension<TrashConfig>,
+    uri: Uri,
+    user: AuthUser,
+    AppQuery(params): AppQuery<PageParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let page = params.offset_page()?;
+
+    let rows = sqlx::query!(
+        r#"SELECT id, title, author, url, blog_tag_names(id) AS "tags!", likes, comment_count, created_at, updated_at, version,
+            blog_user(user_id) AS "user: SqlJson<BlogUser>",
+            deleted_at AS "deleted_at!"
+        FROM blogs
+        WHERE deleted_at IS NOT NULL
+            AND (user_id = $1 OR EXISTS (SELECT 1 FROM users WHERE id = $1 AND is_admin))
+        ORDER BY deleted_at DESC, id DESC
+        LIMIT $2 OFFSET $3"#,
+        user.id,
+        page.fetch_limit(),
+        params.offset.unwrap_or(0)
+    )
+    .fetch_all(&pool)
+    .await?;
+    let total = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM blogs
+        WHERE deleted_at IS NOT NULL
+            AND (user_id = $1 OR EXISTS (SELECT 1 FROM users WHERE id = $1 AND is_admin))"#,
+        user.id
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    let rows = rows
+        .into_iter()
+        .map(|row| TrashedBlog {
+            blog: Blog {
+                id: row.id,
+                title: row.title,
+                author: row.author,
+                url: row.url,
+                tags: row.tags,
+                likes: row.likes,
+                comment_count: row.comment_count,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+                version: row.version,
+                user: row.user,
+            },
+            deleted_at: row.deleted_at,
+            purge_at: row.deleted_at + config.retention,
+        })
+        .collect();
+    let result = page.finish(rows, |_| vec![]);
+    let headers = result.headers(&uri, page.limit, total);
+
+    Ok((StatusCode::OK, headers, Json(result.items)))
+}
+
+/// Restore a blog
+///
+/// Takes a blog out of the trash given the id, with its likes, comments and tags. Only the user
+/// who submitted the blog or an admin may restore it
+#[utoipa::path(
+    post,
+    path = "/blogs/{id}/restore",
+    params(("id" = i32, Path, description = "Blog id")),
+    security(("bearer" = [])),
+    responses(
+        (status = 500, description = "Internal server error", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 404, description = "Blog not found in the trash", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 400, description = "Invalid blog id", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 401, description = "Missing, expired or invalid token", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 403, description = "Blog was submitted by another user", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 409, description = "Another blog with the same url was submitted since", body=ProblemDetails, content_type="application/problem+json",
+            headers(("Location" = String, description = "Path of the other blog"))
+        ),
+        (status = 200, description = "Blog restored successfully", body=Blog)
+    )
+)]
+pub async fn restore_blog(
+    Extension(pool): Extension<PgPool>,
+    user: AuthUser,
+    TrashedBlogOwner(id): TrashedBlogOwner,
+) -> Result<impl IntoResponse, AppError> {
+    let mut tx = pool.begin().await?;
+    let url = sqlx::query_scalar!(
+        "SELECT url FROM blogs WHERE id = $1 AND deleted_at IS NOT NULL FOR UPDATE",
+        id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| blog_not_found(id))?;
+    check_duplicate_url(&mut tx, &url, Some(id)).await?;
+    sqlx::query!("UPDATE blogs SET deleted_at = NULL WHERE id = $1", id)
+        .execute(&mut *tx)
+        .await?;
+    record_revision(&mut tx, id, user.id, RevisionAction::Restore, None).await?;
+    let blog = fetch_blog(&mut *tx, id)
+        .await?
+        .ok_or_else(|| blog_not_found(id))?;
+    tx.commit().await?;
+
+    Ok((StatusCode::OK, Json(blog)))
+}
+
+/// Purge the trash
+///
+/// Removes for good, with their likes, comments and tags, the blogs that stayed in the trash
+/// longer than the retention. The server also does this every hour. Only admins may purge the
+/// trash
+#[utoipa::path(
+    post,
+    path = "/trash/purge",
+    security(("bearer" = [])),
+    responses(
+        (status = 500, description = "Internal server error", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 401, description = "Missing, expired or invalid token", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 403, description = "User is not an admin", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 200, description = "Trash purged successfully", body=PurgeReport)
+    )
+)]
+pub async fn purge_trash(
+    Extension(pool): Extension<PgPool>,
+    Extension(config): Extension<TrashConfig>,
+    _admin: AdminUser,
+) -> Result<impl IntoResponse, AppError> {
+    let purged = purge_expired(&pool, config.retention).await?;
+
+    Ok((StatusCode::OK, Json(PurgeReport { purged })))
+}
+
+/// Deletes the blogs trashed longer than `retention` ago, returns how many
+pub async fn purge_expired(pool: &PgPool, retention: Duration) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM blogs WHERE deleted_at < $1",
+        Utc::now() - retention
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Purges the trash every `PURGE_INTERVAL` until `shutdown` is triggered, a purge already
+/// running is finished first
+pub async fn purge_periodically(pool: PgPool, config: TrashConfig, shutdown: Shutdown) {
+    let mut interval = tokio::time::interval(PURGE_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.triggered() => return,
+        }
+        match purge_expired(&pool, config.retention).await {
+            Ok(0) => {}
+            Ok(purged) => info!("Purged {} blogs from the trash", purged),
+            Err(e) => error!("Failed to purge the trash: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod trash_api_test {
+    use crate::test_helper::{get_test_blogs, insert_test_values, setup_test_context};
+    use axum::http::StatusCode;
+    use rstest::*;
+    use serde_json::{json, Value};
+
+    // a deleted blog disappears everywhere until it is restored with its likes and comments
+    #[rstest]
+    #[tokio::test]
+    async fn delete_and_restore_blog() {
+        let mut ctx = setup_test_context().await;
+        ctx.login_as_admin("root").await;
+        insert_test_values(&ctx.pool)
+            .await
+            .expect("Expected insert statement to work");
+        ctx.server
+            .post("/blogs/3/comments")
+            .json(&json!({"body": "first"}))
+            .await
+            .assert_status(StatusCode::CREATED);
+
+        ctx.server
+            .delete("/blogs/3")
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        ctx.server
+            .delete("/blogs/3")
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+        for response in [
+            ctx.server.get("/blogs/3").await,
+            ctx.server.get("/blogs/3/comments").await,
+            ctx.server
+                .post("/blogs/3/comments")
+                .json(&json!({"body": "second"}))
+                .await,
+            ctx.server
+                .patch("/comments/1")
+                .json(&json!({"body": "edited"}))
+                .await,
+            ctx.server.post("/blogs/3/like").await,
+            ctx.server
+                .patch("/blogs/3")
+                .json(&json!({"title": "t"}))
+                .await,
+        ] {
+            response.assert_status(StatusCode::NOT_FOUND);
+        }
+        let response = ctx.server.get("/blogs").await;
+        response.assert_header("x-total-count", "5");
+        assert!(response
+            .json::<Vec<Value>>()
+            .iter()
+            .all(|blog| blog["id"] != 3));
+        let stats: Value = ctx.server.get("/stats").await.json();
+        assert_eq!(5, stats["blogs"]);
+        assert_eq!(24, stats["total_likes"]);
+        let search: Value = ctx.server.get("/blogs/search?q=canonical").await.json();
+        assert_eq!(0, search["total"]);
+
+        let response = ctx.server.get("/trash").await;
+        response.assert_status(StatusCode::OK);
+        response.assert_header("x-total-count", "1");
+        let trash: Vec<Value> = response.json();
+        assert_eq!(3, trash[0]["id"]);
+        let deleted_at: chrono::DateTime<chrono::Utc> =
+            serde_json::from_value(trash[0]["deleted_at"].clone()).unwrap();
+        let purge_at: chrono::DateTime<chrono::Utc> =
+            serde_json::from_value(trash[0]["purge_at"].clone()).unwrap();
+        assert_eq!(chrono::Duration::days(30), purge_at - deleted_at);
+
+        let response = ctx.server.post("/blogs/3/restore").await;
+        response.assert_status(StatusCode::OK);
+        let mut expected = serde_json::to_value(&get_test_blogs()[2]).unwrap();
+        expected["comment_count"] = json!(1);
+        response.assert_json(&expected);
+        ctx.server
+            .post("/blogs/3/restore")
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+        ctx.server.get("/trash").await.assert_json(&json!([]));
+        ctx.server
+            .get("/blogs")
+            .await
+            .assert_header("x-total-count", "6");
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // users see and restore only their own deleted blogs, admins every one
+    #[rstest]
+    #[tokio::test]
+    async fn trash_of_each_user() {
+        let mut ctx = setup_test_context().await;
+        ctx.server
+            .get("/trash")
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+
+        ctx.login_as("alice").await;
+        let blog = json!({"title": "t", "author": "a", "url": "http://a.com"});
+        ctx.server.post("/blogs").json(&blog).await;
+        ctx.server
+            .delete("/blogs/1")
+            .await
+            .assert_status(StatusCode::NO_CONTENT);
+        let trash: Vec<Value> = ctx.server.get("/trash").await.json();
+        assert_eq!(1, trash.len());
+        assert_eq!("alice", trash[0]["user"]["username"]);
+
+        ctx.login_as("bob").await;
+        ctx.server.get("/trash").await.assert_json(&json!([]));
+        ctx.server
+            .post("/blogs/1/restore")
+            .await
+            .assert_status(StatusCode::FORBIDDEN);
+
+        ctx.login_as_admin("carol").await;
+        ctx.server
+            .get("/trash")
+            .await
+            .assert_header("x-total-count", "1");
+        ctx.server
+            .post("/blogs/1/restore")
+            .await
+            .assert_status(StatusCode::OK);
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // only the blogs trashed longer than the retention are purged, by admins
+    #[rstest]
+    #[tokio::test]
+    async fn purge_old_blogs() {
+        let mut ctx = setup_test_context().await;
+        ctx.login_as_admin("root").await;
+        insert_test_values(&ctx.pool)
+            .await
+            .expect("Expected insert statement to work");
+        sqlx::query(
+            "UPDATE blogs SET deleted_at = CASE id WHEN 1 THEN now() - INTERVAL '31 days'
+                ELSE now() - INTERVAL '29 days' END
+            WHERE id IN (1, 2)",
+        )
+        .execute(&ctx.pool)
+        .await
+        .unwrap();
+
+        let response = ctx.server.post("/trash/purge").await;
+        response.assert_status(StatusCode::OK);
+        response.assert_json(&json!({"purged": 1}));
+        let trash: Vec<Value> = ctx.server.get("/trash").await.json();
+        assert_eq!(
+            vec![2],
+            trash
+                .iter()
+                .map(|blog| blog["id"].clone())
+                .collect::<Vec<_>>()
+        );
+        ctx.server
+            .post("/blogs/1/restore")
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+
+        ctx.login_as("alice").await;
+        ctx.server
+            .post("/trash/purge")
+            .await
+            .assert_status(StatusCode::FORBIDDEN);
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+}
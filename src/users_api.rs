@@ -0,0 +1,253 @@
+use axum::{
+    http::{StatusCode, Uri},
+    response::IntoResponse,
+    Extension, Json,
+};
+use sqlx::{types::Json as SqlJson, PgPool};
+
+use crate::{
+    errors::{AppError, ProblemDetails},
+    extractors::{AppPath, AppQuery, ValidatedJson},
+    models::{User, UserBlog, UserPostPayload},
+    pagination::PageParams,
+    passwords::hash_password,
+};
+
+/// Register a user
+///
+/// Creates a user with a unique username, the password is only stored as a hash
+#[utoipa::path(
+    post,
+    path = "/users",
+    request_body = UserPostPayload,
+    responses(
+        (status = 500, description = "Internal server error", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 400, description = "Malformed or invalid request body", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 415, description = "Request body is not JSON", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 409, description = "Username is taken", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 201, description = "User created successfully", body=User)
+    )
+)]
+pub async fn create_user(
+    Extension(pool): Extension<PgPool>,
+    ValidatedJson(body): ValidatedJson<UserPostPayload>,
+) -> Result<impl IntoResponse, AppError> {
+    let password_hash = hash_password(body.password).await?;
+
+    let user = sqlx::query_as!(
+        User,
+        r#"INSERT INTO users (username, name, password_hash) VALUES ($1, $2, $3)
+        RETURNING id, username, name, created_at, user_blogs(id) AS "blogs!: SqlJson<Vec<UserBlog>>""#,
+        body.username,
+        body.name.trim(),
+        password_hash
+    )
+    .fetch_one(&pool)
+    .await
+    .map_err(|e| match &e {
+        sqlx::Error::Database(db_err) if db_err.constraint() == Some("users_username_key") => {
+            AppError::Conflict(format!("Username `{}` is taken", body.username))
+        }
+        _ => AppError::from(e),
+    })?;
+
+    Ok((StatusCode::CREATED, Json(user)))
+}
+
+/// Get all users
+///
+/// Returns a page of users with the blogs they submitted, by id. The total number of users
+/// is returned in `X-Total-Count`
+#[utoipa::path(
+    get,
+    path = "/users",
+    params(PageParams),
+    responses(
+        (status = 500, description = "Internal server error", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 400, description = "Invalid paging parameters", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 200, description = "Users retrieved successfully", body=[User],
+            headers(
+                ("X-Total-Count" = i64, description = "Total number of users"),
+                ("Link" = String, description = "Links to the `prev` and `next` pages")
+            )
+        )
+    )
+)]
+pub async fn get_users(
+    Extension(pool): Extension<PgPool>,
+    uri: Uri,
+    AppQuery(params): AppQuery<PageParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let page = params.offset_page()?;
+
+    let rows = sqlx::query_as!(
+        User,
+        r#"SELECT id, username, name, created_at, user_blogs(id) AS "blogs!: SqlJson<Vec<UserBlog>>"
+        FROM users ORDER BY id LIMIT $1 OFFSET $2"#,
+        page.fetch_limit(),
+        params.offset.unwrap_or(0)
+    )
+    .fetch_all(&pool)
+    .await?;
+    let total = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM users"#)
+        .fetch_one(&pool)
+        .await?;
+
+    let result = page.finish(rows, |_| vec![]);
+    let headers = result.headers(&uri, page.limit, total);
+
+    Ok((StatusCode::OK, headers, Json(result.items)))
+}
+
+/// Get one user
+///
+/// Returns a user with the blogs they submitted given the id
+#[utoipa::path(
+    get,
+    path = "/users/{id}",
+    params(("id" = i32, Path, description = "User id")),
+    responses(
+        (status = 500, description = "Internal server error", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 404, description = "User not found", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 400, description = "Invalid user id", body=ProblemDetails, content_type="application/problem+json"),
+        (status = 200, description = "User retrieved successfully", body=User)
+    )
+)]
+pub async fn get_user(
+    Extension(pool): Extension<PgPool>,
+    AppPath(id): AppPath<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let user = sqlx::query_as!(
+        User,
+        r#"SELECT id, username, name, created_at, user_blogs(id) AS "blogs!: SqlJson<Vec<UserBlog>>"
+        FROM users WHERE id = $1"#,
+        id
+    )
+    .fetch_optional(&pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound(format!("User with id={} not found", id)))?;
+
+    Ok((StatusCode::OK, Json(user)))
+}
+
+#[cfg(test)]
+mod users_api_test {
+    use crate::test_helper::{insert_test_values, setup_test_context};
+    use axum::http::StatusCode;
+    use rstest::*;
+    use serde_json::{json, Value};
+
+    // a registered user is listed without their password
+    #[rstest]
+    #[tokio::test]
+    async fn create_user() {
+        let ctx = setup_test_context().await;
+
+        let response = ctx
+            .server
+            .post("/users")
+            .json(&json!({"username": "mluukkai", "name": "Matti Luukkainen", "password": "salainen"}))
+            .await;
+
+        response.assert_status(StatusCode::CREATED);
+        let user: Value = response.json();
+        assert_eq!(1, user["id"]);
+        assert_eq!("mluukkai", user["username"]);
+        assert_eq!(json!([]), user["blogs"]);
+        assert!(user.get("password").is_none() && user.get("password_hash").is_none());
+
+        let hash: String = sqlx::query_scalar("SELECT password_hash FROM users WHERE id = 1")
+            .fetch_one(&ctx.pool)
+            .await
+            .unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+
+        let response = ctx.server.get("/users").await;
+        response.assert_status(StatusCode::OK);
+        response.assert_json(&json!([user]));
+        assert_eq!("1", response.header("x-total-count"));
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // invalid users are rejected, taken usernames conflict
+    #[rstest]
+    #[case::short_username(json!({"username": "ml", "name": "M", "password": "salainen"}), StatusCode::BAD_REQUEST, "username")]
+    #[case::invalid_username(json!({"username": "m luukkai", "name": "M", "password": "salainen"}), StatusCode::BAD_REQUEST, "username")]
+    #[case::short_password(json!({"username": "mluukkai", "name": "M", "password": "sala"}), StatusCode::BAD_REQUEST, "password")]
+    #[case::missing_name(json!({"username": "mluukkai", "password": "salainen"}), StatusCode::BAD_REQUEST, "name")]
+    #[case::taken_username(json!({"username": "root", "name": "M", "password": "salainen"}), StatusCode::CONFLICT, "")]
+    #[tokio::test]
+    async fn create_user_errors(
+        #[case] body: Value,
+        #[case] expected_status_code: StatusCode,
+        #[case] field: &str,
+    ) {
+        let ctx = setup_test_context().await;
+        ctx.server
+            .post("/users")
+            .json(&json!({"username": "root", "name": "Superuser", "password": "sekret12"}))
+            .await
+            .assert_status(StatusCode::CREATED);
+
+        let response = ctx.server.post("/users").json(&body).await;
+
+        response.assert_status(expected_status_code);
+        let problem: Value = response.json();
+        if !field.is_empty() {
+            assert!(problem["errors"].get(field).is_some());
+        }
+        ctx.server
+            .get("/users")
+            .await
+            .assert_header("x-total-count", "1");
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+
+    // users embed their blogs and blogs embed their user
+    #[rstest]
+    #[tokio::test]
+    async fn users_and_blogs_embed_each_other() {
+        let ctx = setup_test_context().await;
+        insert_test_values(&ctx.pool)
+            .await
+            .expect("Expected insert statement to work");
+        ctx.server
+            .post("/users")
+            .json(&json!({"username": "unclebob", "name": "Robert C. Martin", "password": "cleancode"}))
+            .await
+            .assert_status(StatusCode::CREATED);
+        sqlx::query("UPDATE blogs SET user_id = 1 WHERE author = 'Robert C. Martin'")
+            .execute(&ctx.pool)
+            .await
+            .unwrap();
+
+        let user: Value = ctx.server.get("/users/1").await.json();
+        let blog_ids: Vec<i64> = user["blogs"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|blog| blog["id"].as_i64().unwrap())
+            .collect();
+        assert_eq!(vec![4, 5, 6], blog_ids);
+
+        let blog: Value = ctx.server.get("/blogs/4").await.json();
+        assert_eq!(
+            json!({"id": 1, "username": "unclebob", "name": "Robert C. Martin"}),
+            blog["user"]
+        );
+        let blog: Value = ctx.server.get("/blogs/1").await.json();
+        assert_eq!(Value::Null, blog["user"]);
+
+        ctx.server
+            .get("/users/42")
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+
+        // cleanup
+        ctx.cleanup().await;
+    }
+}